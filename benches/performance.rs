@@ -81,7 +81,7 @@ fn bench_replacement(c: &mut Criterion) {
         b.iter(|| {
             let (_, count) = perform_replacement_utf8_optimized(
                 black_box(&content_large),
-                black_box(&large_pattern),
+                black_box(large_pattern),
                 black_box("replacement"),
                 black_box(false)
             );
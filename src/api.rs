@@ -0,0 +1,375 @@
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use encoding_rs::Encoding;
+use regex::Regex;
+use crate::core::{ReplacementConfig, build_configured_regex, perform_configured_replacement};
+use crate::core::bytes::{escape_bytes, unescape_bytes};
+use crate::error::{Error, Result, Warning, check_strict};
+use crate::io::encoding::{decode_content, encode_text, looks_binary, output_format};
+use crate::io::journal::Journal;
+use crate::io::report::{Counted, Engine, FileReport, STDIN_NAME};
+use crate::io::streaming::{process_file_by_size, stream_replacement};
+
+/// Builds a [`Replacer`]
+#[derive(Debug, Clone, Default)]
+pub struct ReplacerBuilder {
+    pattern: String,
+    replacement: String,
+    encoding: Option<String>,
+    config: ReplacementConfig,
+    journal: Option<Arc<Journal>>,
+}
+
+impl ReplacerBuilder {
+    /// The string, or regex with `ReplacementConfig::with_regex`, to search for; may be left
+    /// empty when the configuration carries a pattern set
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = pattern.into();
+        self
+    }
+
+    pub fn replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Decode with the encoding of this label (e.g. `latin1`, `Shift_JIS`) instead of detecting it
+    pub fn encoding(mut self, label: impl Into<String>) -> Self {
+        self.encoding = Some(label.into());
+        self
+    }
+
+    pub fn config(mut self, config: ReplacementConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Record every file written in this run journal so the run can be undone
+    pub fn journal(mut self, journal: Option<Arc<Journal>>) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Compile the pattern and resolve the encoding
+    ///
+    /// An unknown encoding label falls back to UTF-8 with a warning, reported by every
+    /// replacement that decodes, or fails the build in strict mode.
+    pub fn build(self) -> Result<Replacer> {
+        if self.pattern.is_empty() && self.config.pattern_set.is_none() {
+            return Err(Error::InvalidInput("the pattern to replace is empty".to_string()));
+        }
+        let regex = build_configured_regex(&self.pattern, &self.config).map_err(Error::invalid_input)?;
+
+        let mut warnings = Vec::new();
+        let encoding = self.encoding.map(|label| {
+            Encoding::for_label(label.as_bytes()).unwrap_or_else(|| {
+                warnings.push(Warning::UnknownEncoding { label });
+                encoding_rs::UTF_8
+            })
+        });
+        check_strict(self.config.strict, None, &warnings)?;
+
+        Ok(Replacer {
+            pattern: self.pattern,
+            replacement: self.replacement,
+            encoding,
+            warnings,
+            regex,
+            config: self.config,
+            journal: self.journal,
+        })
+    }
+}
+
+/// A pattern, its replacement and how to apply them, reusable on strings, buffers, streams
+/// and files
+///
+/// Nothing is printed: each method returns what it did in a [`ReplaceOutcome`].
+#[derive(Debug, Clone)]
+pub struct Replacer {
+    pattern: String,
+    replacement: String,
+    encoding: Option<&'static Encoding>,
+    /// Warnings raised while building, e.g. about the encoding label
+    warnings: Vec<Warning>,
+    regex: Option<Regex>,
+    config: ReplacementConfig,
+    journal: Option<Arc<Journal>>,
+}
+
+/// The result of a replacement and the report of what it did
+#[derive(Debug, Clone)]
+pub struct ReplaceOutcome<T = ()> {
+    /// The replaced content, for in-memory input; `()` when it was written out
+    pub output: T,
+    pub report: FileReport,
+    /// The unified diff of the changes of a dry run on a file, which is left untouched
+    pub diff: Option<String>,
+}
+
+impl<T> ReplaceOutcome<T> {
+    pub fn replacements(&self) -> usize {
+        self.report.replacements
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.report.warnings
+    }
+}
+
+impl Replacer {
+    pub fn builder() -> ReplacerBuilder {
+        ReplacerBuilder::default()
+    }
+
+    pub fn config(&self) -> &ReplacementConfig {
+        &self.config
+    }
+
+    /// The encoding given to the builder; `None` means it is detected
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    /// Warnings raised while building, e.g. about the encoding label
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Replace in a string
+    pub fn replace_str(&self, text: &str) -> Result<ReplaceOutcome<String>> {
+        let start_time = Instant::now();
+        let config = self.config.for_file();
+        let mut report = FileReport::new(STDIN_NAME);
+        report.bytes_in = text.len() as u64;
+        report.engine = if text.len() > config.parallel_threshold { Engine::Parallel } else { Engine::Sequential };
+
+        let (output, replacements) =
+            perform_configured_replacement(text, &self.pattern, &self.replacement, self.regex.as_ref(), &config);
+        report.replacements = replacements;
+        report.bytes_out = output.len() as u64;
+        report.elapsed = start_time.elapsed();
+        Ok(ReplaceOutcome { output, report, diff: None })
+    }
+
+    /// Replace in encoded content, decoded and encoded back like a file, BOM included
+    ///
+    /// Without an encoding it is detected, and content that looks binary or whose encoding is too
+    /// uncertain for the minimum confidence is returned as is. In byte mode the content is read as
+    /// UTF-8 and the bytes that are not are kept as they were.
+    pub fn replace_bytes(&self, bytes: &[u8]) -> Result<ReplaceOutcome<Vec<u8>>> {
+        let start_time = Instant::now();
+        let config = self.config.for_file();
+        let mut report = FileReport::new(STDIN_NAME);
+        report.warnings = self.warnings.clone();
+        report.bytes_in = bytes.len() as u64;
+        report.bytes_out = report.bytes_in;
+        let unchanged = |mut report: FileReport| {
+            report.elapsed = start_time.elapsed();
+            Ok(ReplaceOutcome { output: bytes.to_vec(), report, diff: None })
+        };
+
+        if config.occurrences_exhausted() {
+            report.skipped = Some("occurrences exhausted");
+            return unchanged(report);
+        }
+        if self.encoding.is_none() && looks_binary(bytes) {
+            report.skipped = Some("binary");
+            return unchanged(report);
+        }
+
+        let (content, encoding, had_bom, confidence) = if config.byte_mode {
+            (escape_bytes(bytes), encoding_rs::UTF_8, false, None)
+        } else {
+            decode_content(bytes, self.encoding, &mut report.warnings)
+        };
+        report.encoding = Some(encoding.name());
+        report.bom = had_bom;
+        report.confidence = confidence;
+        if config.rejects_confidence(confidence) {
+            report.skipped = Some("ambiguous encoding");
+            return unchanged(report);
+        }
+        check_strict(config.strict, None, &report.warnings)?;
+
+        report.engine = if content.len() > config.parallel_threshold { Engine::Parallel } else { Engine::Sequential };
+        let (processed, replacements) =
+            perform_configured_replacement(&content, &self.pattern, &self.replacement, self.regex.as_ref(), &config);
+        report.replacements = replacements;
+        let (output_encoding, bom) = output_format(encoding, had_bom, &config);
+        if output_encoding != encoding {
+            report.output_encoding = Some(output_encoding.name());
+        }
+        // Also when a BOM is added or removed
+        let transcoding = output_encoding != encoding || had_bom == bom.is_empty();
+        if replacements == 0 && !transcoding && processed == content {
+            return unchanged(report);
+        }
+
+        let (encoded, had_encoding_errors) = if config.byte_mode {
+            (unescape_bytes(&processed), false)
+        } else {
+            encode_text(output_encoding, &processed)
+        };
+        if had_encoding_errors {
+            report.warnings.push(Warning::UnencodableCharacters { encoding: output_encoding.name() });
+            check_strict(config.strict, None, &report.warnings)?;
+        }
+        let output = [bom, &encoded].concat();
+        report.bytes_out = output.len() as u64;
+        report.elapsed = start_time.elapsed();
+        Ok(ReplaceOutcome { output, report, diff: None })
+    }
+
+    /// Filter a stream, holding at most a few buffers in memory
+    ///
+    /// Without an encoding or a BOM the stream is processed as UTF-8, byte for byte. A dry run
+    /// makes no difference here: the caller chooses where the output goes.
+    pub fn replace_reader_to_writer<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<ReplaceOutcome>
+    where
+        R: Read + ?Sized,
+        W: Write + ?Sized,
+    {
+        let start_time = Instant::now();
+        let config = self.config.for_file();
+        let encoding = self.encoding.filter(|_| !config.byte_mode).unwrap_or(encoding_rs::UTF_8);
+        let mut report = FileReport::new(STDIN_NAME);
+        report.warnings = self.warnings.clone();
+        report.engine = Engine::Streaming;
+
+        let mut reader = Counted::new(BufReader::with_capacity(config.buffer_size, reader));
+        let mut writer = Counted::new(writer);
+        report.replacements = stream_replacement(
+            &mut reader,
+            &mut writer,
+            &self.pattern,
+            &self.replacement,
+            self.regex.as_ref(),
+            encoding,
+            &config,
+            &mut report
+        )?.0;
+        report.bytes_in = reader.count;
+        report.bytes_out = writer.count;
+        report.elapsed = start_time.elapsed();
+        Ok(ReplaceOutcome { output: (), report, diff: None })
+    }
+
+    /// Replace in a file, in place through a temporary file renamed over it
+    ///
+    /// A file without any match is left untouched, as is one that looks binary when the
+    /// encoding is detected. A dry run writes nothing and returns the diff instead.
+    ///
+    /// Like the command line, a file larger than 10 times the buffer size is streamed rather than
+    /// read whole: a dry run only counts its replacements, flagging `diff_skipped` in the report.
+    pub fn replace_path(&self, path: impl AsRef<Path>) -> Result<ReplaceOutcome> {
+        self.replace_file(path.as_ref(), None)
+    }
+
+    /// Replace in a file, writing the result to `output` (even without any match)
+    pub fn replace_path_to(&self, path: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<ReplaceOutcome> {
+        self.replace_file(path.as_ref(), Some(output.as_ref()))
+    }
+
+    fn replace_file(&self, path: &Path, output: Option<&Path>) -> Result<ReplaceOutcome> {
+        let mut report = process_file_by_size(
+            path,
+            output,
+            &self.pattern,
+            &self.replacement,
+            self.regex.as_ref(),
+            self.encoding,
+            &self.config,
+            self.journal.as_deref()
+        )
+            .map_err(|e| e.with_path(path))?;
+        report.warnings.splice(0..0, self.warnings.iter().cloned());
+        let diff = report.diff.clone();
+        Ok(ReplaceOutcome { output: (), report, diff })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_in_memory() {
+        let replacer = Replacer::builder().pattern("café").replacement("thé").build().unwrap();
+        let outcome = replacer.replace_str("un café, deux cafés").unwrap();
+        assert_eq!(outcome.output, "un thé, deux thés");
+        assert_eq!(outcome.replacements(), 2);
+
+        // Detected Windows-1252 is written back as such
+        let outcome = replacer.replace_bytes(b"un caf\xE9").unwrap();
+        assert_eq!(outcome.output, b"un th\xE9");
+        assert_eq!(outcome.warnings(), [Warning::GuessedEncoding { encoding: "windows-1252" }]);
+
+        // A BOM is kept, and content without a match comes back byte for byte
+        assert_eq!(replacer.replace_bytes(b"\xEF\xBB\xBFcaf\xC3\xA9").unwrap().output, b"\xEF\xBB\xBFth\xC3\xA9");
+        assert_eq!(replacer.replace_bytes(b"tea\r\n").unwrap().output, b"tea\r\n");
+
+        let mut output = Vec::new();
+        let outcome = replacer.replace_reader_to_writer(&mut "café\n".repeat(100).as_bytes(), &mut output).unwrap();
+        assert_eq!(outcome.replacements(), 100);
+        assert_eq!(outcome.report.bytes_out, output.len() as u64);
+        assert_eq!(String::from_utf8(output).unwrap(), "thé\n".repeat(100));
+    }
+
+    #[test]
+    fn test_replace_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "old value\n").unwrap();
+
+        let config = ReplacementConfig::new().with_dry_run(true);
+        let preview = Replacer::builder().pattern("old").replacement("new").config(config).build().unwrap();
+        let outcome = preview.replace_path(&path).unwrap();
+        assert!(outcome.diff.unwrap().contains("-old value\n+new value\n"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old value\n");
+
+        let replacer = Replacer::builder().pattern("old").replacement("new").build().unwrap();
+        assert_eq!(replacer.replace_path(&path).unwrap().replacements(), 1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new value\n");
+    }
+
+    #[test]
+    fn test_replace_path_streams_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.txt");
+        let content = b"caf\xE9 old\n".repeat(100);
+        std::fs::write(&path, &content).unwrap();
+
+        // Over 10 times the buffer size, with the encoding detected from the head of the file
+        let config = ReplacementConfig::new().with_buffer_size(64).with_regex(true).with_dry_run(true);
+        let preview = Replacer::builder().pattern("o(l)d").replacement("ne$1").config(config.clone()).build().unwrap();
+        let outcome = preview.replace_path(&path).unwrap();
+        assert_eq!(outcome.report.engine, Engine::Streaming);
+        assert_eq!(outcome.replacements(), 100);
+        assert!(outcome.report.diff_skipped && outcome.diff.is_none());
+        assert_eq!(std::fs::read(&path).unwrap(), content);
+
+        let replacer = Replacer::builder().pattern("o(l)d").replacement("ne$1").config(config.with_dry_run(false)).build().unwrap();
+        let outcome = replacer.replace_path(&path).unwrap();
+        assert_eq!(outcome.report.encoding, Some("windows-1252"));
+        assert_eq!(outcome.warnings(), [Warning::GuessedEncoding { encoding: "windows-1252" }]);
+        assert_eq!(std::fs::read(&path).unwrap(), b"caf\xE9 nel\n".repeat(100));
+    }
+
+    #[test]
+    fn test_build_errors() {
+        assert!(matches!(Replacer::builder().replacement("x").build(), Err(Error::InvalidInput(_))));
+
+        let regex = ReplacementConfig::new().with_regex(true);
+        assert!(matches!(Replacer::builder().pattern("(").config(regex).build(), Err(Error::InvalidInput(_))));
+
+        let replacer = Replacer::builder().pattern("a").encoding("klingon").build().unwrap();
+        assert_eq!(replacer.encoding(), Some(encoding_rs::UTF_8));
+        assert_eq!(replacer.replace_bytes(b"a").unwrap().warnings().len(), 1);
+        let strict = ReplacementConfig::new().with_strict(true);
+        let error = Replacer::builder().pattern("a").encoding("klingon").config(strict).build().unwrap_err();
+        assert!(matches!(error, Error::Warning { warning: Warning::UnknownEncoding { .. }, .. }));
+    }
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use memchr::memchr;
+use regex::Regex;
+use super::config::ReplacementConfig;
+use super::specialized::perform_configured_replacement;
+
+/// Private-use characters U+10FF00 to U+10FFFF stand for the bytes 0x00 to 0xFF of content that
+/// is not UTF-8, so that it can go through the text engines and come back byte for byte
+const ESCAPE_BASE: u32 = 0x10FF00;
+
+/// Lead byte of the UTF-8 encoding of the escape characters, which content without it cannot hold
+const ESCAPE_LEAD: u8 = 0xF4;
+
+fn is_escape(c: char) -> bool {
+    c as u32 >= ESCAPE_BASE
+}
+
+fn push_escaped_bytes(text: &mut String, bytes: &[u8]) {
+    text.extend(bytes.iter().map(|&byte| char::from_u32(ESCAPE_BASE + byte as u32).unwrap()));
+}
+
+/// Read mostly UTF-8 content as text without losing anything: each invalid byte becomes one
+/// escape character, as do the bytes of the rare genuine characters in the escape range
+pub fn escape_bytes(bytes: &[u8]) -> Cow<'_, str> {
+    if memchr(ESCAPE_LEAD, bytes).is_none()
+        && let Ok(text) = std::str::from_utf8(bytes) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if is_escape(c) {
+                push_escaped_bytes(&mut text, c.encode_utf8(&mut [0; 4]).as_bytes());
+            } else {
+                text.push(c);
+            }
+        }
+        push_escaped_bytes(&mut text, chunk.invalid());
+    }
+    Cow::Owned(text)
+}
+
+/// The bytes of text read with `escape_bytes`
+pub fn unescape_bytes(text: &str) -> Cow<'_, [u8]> {
+    if memchr(ESCAPE_LEAD, text.as_bytes()).is_none() {
+        return Cow::Borrowed(text.as_bytes());
+    }
+
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        if is_escape(c) {
+            bytes.push((c as u32 - ESCAPE_BASE) as u8);
+        } else {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+/// Escaped text as it would print, with U+FFFD for the bytes that are not UTF-8
+pub fn display_escaped(text: &str) -> Cow<'_, str> {
+    match unescape_bytes(text) {
+        Cow::Borrowed(_) => Cow::Borrowed(text),
+        Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+    }
+}
+
+/// Replace in raw bytes read as UTF-8, leaving every byte outside the matches as it was
+///
+/// Any engine the configuration selects applies; an invalid byte counts as one character that
+/// no literal pattern matches, though a regex like `.` does. The regex, if any, must come from
+/// `build_configured_regex`.
+pub fn perform_configured_byte_replacement(
+    content: &[u8],
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> (Vec<u8>, usize) {
+    let text = escape_bytes(content);
+    let (result, count) = perform_configured_replacement(&text, pattern, replacement, regex, config);
+    if count == 0 && result == text {
+        return (content.to_vec(), 0);
+    }
+    (unescape_bytes(&result).into_owned(), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_round_trip() {
+        let contents: [&[u8]; 4] = [
+            b"plain text",
+            b"caf\xE9 \xFF\xC3 na\xC3\xAFve",
+            "genuine \u{10FF41} escape".as_bytes(),
+            b"\xF4\x8F\xBD truncated",
+        ];
+        for content in contents {
+            let text = escape_bytes(content);
+            assert_eq!(unescape_bytes(&text), content);
+        }
+        assert!(matches!(escape_bytes(b"plain text"), Cow::Borrowed(_)));
+        assert_eq!(display_escaped(&escape_bytes(b"caf\xE9!")), "caf\u{FFFD}!");
+    }
+
+    #[test]
+    fn test_byte_replacement() {
+        let content = b"name=caf\xE9\nname=na\xEFve\r\n\xFF";
+        let config = ReplacementConfig::new();
+        let (result, count) = perform_configured_byte_replacement(content, "name", "title", None, &config);
+        assert_eq!(result, b"title=caf\xE9\ntitle=na\xEFve\r\n\xFF");
+        assert_eq!(count, 2);
+
+        let config = ReplacementConfig::new().with_regex(true);
+        let regex = crate::core::build_configured_regex(r"=(\S+)", &config).unwrap();
+        let (result, _) = perform_configured_byte_replacement(content, r"=(\S+)", "=[$1]", regex.as_ref(), &config);
+        assert_eq!(result, b"name=[caf\xE9]\nname=[na\xEFve]\r\n\xFF");
+    }
+}
@@ -0,0 +1,266 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use regex_syntax::ast::{self, Ast, ClassSet, ClassSetItem};
+use regex_syntax::hir::{ClassUnicode, ClassUnicodeRange};
+use crate::core::sequential::perform_replacement_ascii_ignore_case;
+use crate::core::specialized::{find_matches, replace_matches};
+
+/// Whether patterns are matched with or without regard to case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Case-insensitive unless a pattern contains an uppercase letter
+    Smart,
+}
+
+impl CaseSensitivity {
+    /// Whether matching these literal patterns ignores case
+    pub fn ignores_case<'a>(self, patterns: impl IntoIterator<Item = &'a str>) -> bool {
+        match self {
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+            CaseSensitivity::Smart => !patterns.into_iter().any(|pattern| pattern.chars().any(char::is_uppercase)),
+        }
+    }
+
+    /// Whether matching this regex ignores case; in smart mode only its literal characters count,
+    /// so escapes like `\W` or `\p{Greek}` don't make it case-sensitive
+    pub fn ignores_case_regex(self, pattern: &str) -> bool {
+        match self {
+            CaseSensitivity::Smart => match ast::parse::Parser::new().parse(pattern) {
+                Ok(ast) => !ast_has_uppercase(&ast),
+                Err(_) => false,
+            },
+            _ => self.ignores_case([pattern]),
+        }
+    }
+}
+
+thread_local! {
+    static FOLD_CACHE: RefCell<HashMap<char, char>> = RefCell::new(HashMap::new());
+}
+
+/// Unicode simple case folding: map a character to the smallest character it is equal to when
+/// case is ignored, so two characters match case-insensitively exactly when their folds are equal.
+/// This is the equivalence the regex crate uses for `(?i)`. ASCII letters fold to uppercase.
+pub fn fold_char(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_uppercase();
+    }
+
+    FOLD_CACHE.with(|cache| {
+        *cache.borrow_mut().entry(c).or_insert_with(|| {
+            let mut class = ClassUnicode::new([ClassUnicodeRange::new(c, c)]);
+            class.case_fold_simple();
+            class.ranges()[0].start()
+        })
+    })
+}
+
+/// Fold every character of a string with `fold_char`
+pub fn fold_str(s: &str) -> String {
+    s.chars().map(fold_char).collect()
+}
+
+/// Case-insensitive literal replacement, with Unicode simple case folding
+///
+/// ASCII content goes through the ASCII fast path; other content is folded into a copy that is
+/// searched and mapped back, so the unmatched text is kept exactly as it was.
+pub fn perform_replacement_ignore_case(content: &str, pattern: &str, replacement: &str) -> (String, usize) {
+    if pattern.is_empty() {
+        return (content.to_string(), 0);
+    }
+
+    let folded_pattern = fold_str(pattern);
+    if content.is_ascii() {
+        // A pattern that still has non-ASCII characters once folded cannot match ASCII text
+        if !folded_pattern.is_ascii() {
+            return (content.to_string(), 0);
+        }
+        let (result, count) = perform_replacement_ascii_ignore_case(
+            content.as_bytes(),
+            folded_pattern.as_bytes(),
+            replacement.as_bytes()
+        );
+        return (String::from_utf8(result).unwrap(), count);
+    }
+
+    let matches = find_matches(content, pattern, true);
+    replace_matches(content, &matches, |_| Cow::Borrowed(replacement))
+}
+
+/// Case-folded copy of some text, searched in place of the original
+///
+/// Folding may change the length of a character (the Kelvin sign `K` folds to `K`), in which case
+/// an offset table maps folded positions back to the original. Invalid UTF-8 is copied unchanged.
+pub(crate) struct FoldedText {
+    bytes: Vec<u8>,
+    /// Original offset of the character each folded byte belongs to, plus the end; `None` when
+    /// folded and original offsets are the same
+    offsets: Option<Vec<usize>>,
+}
+
+impl FoldedText {
+    pub(crate) fn new(content: &[u8]) -> Self {
+        if content.is_ascii() {
+            return FoldedText { bytes: content.to_ascii_uppercase(), offsets: None };
+        }
+
+        let mut bytes = Vec::with_capacity(content.len());
+        let mut same_length = true;
+        for chunk in content.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                let folded = fold_char(c);
+                same_length &= folded.len_utf8() == c.len_utf8();
+                bytes.extend_from_slice(folded.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            bytes.extend_from_slice(chunk.invalid());
+        }
+        if same_length {
+            return FoldedText { bytes, offsets: None };
+        }
+
+        let mut offsets = Vec::with_capacity(bytes.len() + 1);
+        let mut original = 0;
+        for chunk in content.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                offsets.extend(std::iter::repeat_n(original, fold_char(c).len_utf8()));
+                original += c.len_utf8();
+            }
+            for _ in chunk.invalid() {
+                offsets.push(original);
+                original += 1;
+            }
+        }
+        offsets.push(original);
+        FoldedText { bytes, offsets: Some(offsets) }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Original offset of a character boundary of the folded text, such as a match start or end
+    pub(crate) fn original_offset(&self, pos: usize) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets[pos],
+            None => pos,
+        }
+    }
+
+    /// Original offset of the start of the character containing the folded position `pos`
+    pub(crate) fn original_floor(&self, mut pos: usize) -> usize {
+        if self.offsets.is_none() {
+            let mut steps = 0;
+            while pos > 0 && pos < self.bytes.len() && self.bytes[pos] & 0xC0 == 0x80 && steps < 3 {
+                pos -= 1;
+                steps += 1;
+            }
+        }
+        self.original_offset(pos)
+    }
+}
+
+/// Length of `bytes` without a UTF-8 sequence cut short at the end
+pub(crate) fn complete_utf8_len(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(4) {
+        let byte = bytes[bytes.len() - back];
+        if byte < 0x80 {
+            break;
+        }
+        if byte >= 0xC0 {
+            let needed = if byte >= 0xF0 { 4 } else if byte >= 0xE0 { 3 } else { 2 };
+            return if back < needed { bytes.len() - back } else { bytes.len() };
+        }
+    }
+    bytes.len()
+}
+
+fn ast_has_uppercase(ast: &Ast) -> bool {
+    match ast {
+        Ast::Literal(literal) => literal.c.is_uppercase(),
+        Ast::ClassBracketed(class) => class_set_has_uppercase(&class.kind),
+        Ast::Repetition(repetition) => ast_has_uppercase(&repetition.ast),
+        Ast::Group(group) => ast_has_uppercase(&group.ast),
+        Ast::Alternation(alternation) => alternation.asts.iter().any(ast_has_uppercase),
+        Ast::Concat(concat) => concat.asts.iter().any(ast_has_uppercase),
+        _ => false,
+    }
+}
+
+fn class_set_has_uppercase(set: &ClassSet) -> bool {
+    match set {
+        ClassSet::Item(item) => class_item_has_uppercase(item),
+        ClassSet::BinaryOp(op) => class_set_has_uppercase(&op.lhs) || class_set_has_uppercase(&op.rhs),
+    }
+}
+
+fn class_item_has_uppercase(item: &ClassSetItem) -> bool {
+    match item {
+        ClassSetItem::Literal(literal) => literal.c.is_uppercase(),
+        ClassSetItem::Range(range) => range.start.c.is_uppercase() || range.end.c.is_uppercase(),
+        ClassSetItem::Bracketed(class) => class_set_has_uppercase(&class.kind),
+        ClassSetItem::Union(union) => union.items.iter().any(class_item_has_uppercase),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold_char() {
+        assert_eq!(fold_char('a'), 'A');
+        assert_eq!(fold_char('\u{212A}'), 'K'); // Kelvin sign
+        assert_eq!(fold_char('ſ'), 'S');
+        assert_eq!(fold_char('ς'), fold_char('Σ'));
+        assert_eq!(fold_char('é'), fold_char('É'));
+        assert_eq!(fold_char('ß'), fold_char('ẞ'));
+        assert_eq!(fold_char('1'), '1');
+    }
+
+    #[test]
+    fn test_replacement_ignore_case() {
+        assert_eq!(perform_replacement_ignore_case("Hello HELLO hello", "hello", "hi"), ("hi hi hi".to_string(), 3));
+        assert_eq!(perform_replacement_ignore_case("ÉTÉ été", "été", "x"), ("x x".to_string(), 2));
+        // Folding changes the length of the Kelvin sign and the long s
+        assert_eq!(perform_replacement_ignore_case("é \u{212A}elvin ſun", "kelvin", "K"), ("é K ſun".to_string(), 1));
+        assert_eq!(perform_replacement_ignore_case("\u{212A}ſ é", "ks", "x"), ("x é".to_string(), 1));
+        assert_eq!(perform_replacement_ignore_case("sun", "ſun", "moon"), ("moon".to_string(), 1));
+        assert_eq!(perform_replacement_ignore_case("ascii", "é", "e"), ("ascii".to_string(), 0));
+    }
+
+    #[test]
+    fn test_ignore_case_agrees_with_regex() {
+        let content = "Straße STRASSE ſtraße \u{212A}elvin kelvin ΣΊΣΥΦΟΣ σίσυφος ς ÅNGSTRÖM \u{212B}ngström";
+        for pattern in ["straße", "kelvin", "σίσυφος", "Σ", "ångström", "s", "k"] {
+            let regex = regex::RegexBuilder::new(&regex::escape(pattern)).case_insensitive(true).build().unwrap();
+            let expected = (regex.replace_all(content, "<>").into_owned(), regex.find_iter(content).count());
+            assert_eq!(perform_replacement_ignore_case(content, pattern, "<>"), expected, "pattern {pattern:?}");
+        }
+    }
+
+    #[test]
+    fn test_smart_case() {
+        assert!(CaseSensitivity::Smart.ignores_case(["hello"]));
+        assert!(!CaseSensitivity::Smart.ignores_case(["hello", "World"]));
+        assert!(!CaseSensitivity::Smart.ignores_case(["été", "Été"]));
+        assert!(CaseSensitivity::Smart.ignores_case_regex(r"\W+\p{Greek}[a-z]"));
+        assert!(!CaseSensitivity::Smart.ignores_case_regex(r"(foo|[A-Z]bar)"));
+        assert!(!CaseSensitivity::Sensitive.ignores_case(["hello"]));
+        assert!(CaseSensitivity::Insensitive.ignores_case_regex("Hello"));
+    }
+
+    #[test]
+    fn test_complete_utf8_len() {
+        assert_eq!(complete_utf8_len(b"abc"), 3);
+        assert_eq!(complete_utf8_len("aé".as_bytes()), 3);
+        assert_eq!(complete_utf8_len(&"aé".as_bytes()[..2]), 1);
+        assert_eq!(complete_utf8_len(&"a€".as_bytes()[..3]), 1);
+        assert_eq!(complete_utf8_len(b"a\x80"), 2);
+    }
+}
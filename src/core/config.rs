@@ -1,9 +1,94 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::sync::Arc;
+use encoding_rs::Encoding;
+use super::case::CaseSensitivity;
+use super::lines::LineScope;
+use super::multi::PatternSet;
+use super::occurrences::Occurrences;
+use super::word::WordBoundary;
+
+/// How the original of a modified file is preserved
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum BackupMode {
+    #[default]
+    None,
+    /// Single backup named after the file plus a suffix, overwritten on each run
+    Simple(String),
+    /// GNU-style numbered backups: `file.~1~`, `file.~2~`, ...
+    Numbered,
+    /// Numbered backups for a file that already has some, a simple one with this suffix otherwise
+    Existing(String),
+}
+
+/// Whether the output starts with a byte order mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BomMode {
+    /// Write one if the input had one
+    #[default]
+    Keep,
+    /// Write one whenever the output encoding has one (UTF-8 and UTF-16)
+    Add,
+    Remove,
+}
+
+/// The line endings of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EolMode {
+    /// Leave them as they are; with `eol_agnostic`, those of a replacement follow the text it
+    /// replaces, or else the first one of the input
+    #[default]
+    Keep,
+    Lf,
+    Crlf,
+}
+
+/// Format of the machine-readable report of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A JSON array with one record per file
+    Json,
+    /// One JSON record per line, one line per file
+    Ndjson,
+}
+
 #[derive(Debug, Clone)]
 pub struct ReplacementConfig {
     pub parallel_threshold: usize,
     pub buffer_size: usize,
     pub use_ascii_optimization: bool,
     pub enable_caching: bool,
+    pub use_regex: bool,
+    pub case_sensitivity: CaseSensitivity,
+    /// Only replace whole words of a literal pattern (a pattern set carries its own boundary)
+    pub word_boundary: Option<WordBoundary>,
+    /// Only replace the selected occurrences rather than every match
+    pub occurrences: Option<Occurrences>,
+    /// Only replace matches on the lines this scope selects
+    pub line_scope: Option<LineScope>,
+    /// Replace all the pairs of this set in one pass instead of a single pattern
+    pub pattern_set: Option<Arc<PatternSet>>,
+    pub dry_run: bool,
+    pub diff_context: usize,
+    pub diff_color: bool,
+    pub preserve_timestamps: bool,
+    pub backup: BackupMode,
+    pub backup_dir: Option<PathBuf>,
+    /// Print a machine-readable record per file instead of the human-readable statistics
+    pub report: Option<ReportFormat>,
+    /// Fail on the first warning, before anything is written, instead of reporting it
+    pub strict: bool,
+    /// Replace in the raw bytes read as UTF-8 instead of decoding them, leaving bytes that are
+    /// not UTF-8 as they were
+    pub byte_mode: bool,
+    /// Skip files whose detected encoding has a lower confidence, from 0 to 1, instead of guessing
+    pub min_confidence: Option<f32>,
+    /// Write the output in this encoding rather than the one the input was read in
+    pub output_encoding: Option<&'static Encoding>,
+    pub bom: BomMode,
+    pub eol: EolMode,
+    /// Match as if every CRLF were LF, so that a `\n` in a pattern matches either line ending
+    pub eol_agnostic: bool,
 }
 
 impl Default for ReplacementConfig {
@@ -13,6 +98,26 @@ impl Default for ReplacementConfig {
             buffer_size: 8 * 1024 * 1024,    // 8MB
             use_ascii_optimization: true,
             enable_caching: false,
+            use_regex: false,
+            case_sensitivity: CaseSensitivity::Sensitive,
+            word_boundary: None,
+            occurrences: None,
+            line_scope: None,
+            pattern_set: None,
+            dry_run: false,
+            diff_context: 3,
+            diff_color: false,
+            preserve_timestamps: false,
+            backup: BackupMode::None,
+            backup_dir: None,
+            report: None,
+            strict: false,
+            byte_mode: false,
+            min_confidence: None,
+            output_encoding: None,
+            bom: BomMode::Keep,
+            eol: EolMode::Keep,
+            eol_agnostic: false,
         }
     }
 }
@@ -41,4 +146,131 @@ impl ReplacementConfig {
         self.enable_caching = enabled;
         self
     }
+    
+    pub fn with_regex(mut self, enabled: bool) -> Self {
+        self.use_regex = enabled;
+        self
+    }
+    
+    /// Match case-sensitively, ignoring case, or ignoring it unless a pattern has an uppercase letter
+    pub fn with_case_sensitivity(mut self, case_sensitivity: CaseSensitivity) -> Self {
+        self.case_sensitivity = case_sensitivity;
+        self
+    }
+    
+    pub fn with_word_boundary(mut self, boundary: Option<WordBoundary>) -> Self {
+        self.word_boundary = boundary;
+        self
+    }
+    
+    pub fn with_occurrences(mut self, occurrences: Option<Occurrences>) -> Self {
+        self.occurrences = occurrences;
+        self
+    }
+    
+    pub fn with_line_scope(mut self, scope: Option<LineScope>) -> Self {
+        self.line_scope = scope;
+        self
+    }
+    
+    /// The configuration to process the next file with, where occurrence numbering restarts
+    /// unless it is global
+    pub fn for_file(&self) -> Cow<'_, Self> {
+        match &self.occurrences {
+            Some(occurrences) if !occurrences.is_global() => Cow::Owned(Self {
+                occurrences: Some(occurrences.for_file()),
+                ..self.clone()
+            }),
+            _ => Cow::Borrowed(self),
+        }
+    }
+    
+    /// Whether every selected occurrence has already been seen, so nothing is left to replace
+    pub fn occurrences_exhausted(&self) -> bool {
+        self.occurrences.as_ref().is_some_and(Occurrences::is_exhausted)
+    }
+    
+    /// Whether an encoding detected with `confidence` is below the minimum; a given encoding
+    /// (`None`) never is
+    pub fn rejects_confidence(&self, confidence: Option<f32>) -> bool {
+        matches!((confidence, self.min_confidence), (Some(confidence), Some(min)) if confidence < min)
+    }
+    
+    pub fn with_pattern_set(mut self, set: Option<Arc<PatternSet>>) -> Self {
+        self.pattern_set = set;
+        self
+    }
+    
+    /// Print a unified diff of the changes instead of writing them
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+    
+    pub fn with_diff_context(mut self, lines: usize) -> Self {
+        self.diff_context = lines;
+        self
+    }
+    
+    pub fn with_diff_color(mut self, enabled: bool) -> Self {
+        self.diff_color = enabled;
+        self
+    }
+    
+    /// Keep the original access and modification times when rewriting a file
+    pub fn with_preserve_timestamps(mut self, enabled: bool) -> Self {
+        self.preserve_timestamps = enabled;
+        self
+    }
+    
+    pub fn with_backup(mut self, mode: BackupMode) -> Self {
+        self.backup = mode;
+        self
+    }
+    
+    /// Store backups under this directory instead of next to the original
+    pub fn with_backup_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.backup_dir = dir;
+        self
+    }
+    
+    pub fn with_report(mut self, format: Option<ReportFormat>) -> Self {
+        self.report = format;
+        self
+    }
+    
+    pub fn with_strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+    
+    pub fn with_byte_mode(mut self, enabled: bool) -> Self {
+        self.byte_mode = enabled;
+        self
+    }
+    
+    pub fn with_min_confidence(mut self, confidence: Option<f32>) -> Self {
+        self.min_confidence = confidence;
+        self
+    }
+    
+    pub fn with_output_encoding(mut self, encoding: Option<&'static Encoding>) -> Self {
+        self.output_encoding = encoding;
+        self
+    }
+    
+    pub fn with_bom(mut self, bom: BomMode) -> Self {
+        self.bom = bom;
+        self
+    }
+    
+    pub fn with_eol(mut self, eol: EolMode) -> Self {
+        self.eol = eol;
+        self
+    }
+    
+    pub fn with_eol_agnostic(mut self, enabled: bool) -> Self {
+        self.eol_agnostic = enabled;
+        self
+    }
 }
@@ -0,0 +1,447 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io;
+use memchr::{memchr, memchr_iter};
+
+/// Text with every CRLF turned into LF, so that a `\n` in a pattern matches either
+pub fn normalize_line_endings(text: &str) -> Cow<'_, str> {
+    if memchr(b'\r', text.as_bytes()).is_none() {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(text.replace("\r\n", "\n"))
+}
+
+/// Text with every line ending turned into CRLF (`crlf`) or LF; a lone CR is left alone
+pub fn convert_line_endings(text: &str, crlf: bool) -> Cow<'_, str> {
+    let bytes = text.as_bytes();
+    if !crlf {
+        return normalize_line_endings(text);
+    }
+    let lone_lf = |i: usize| i == 0 || bytes[i - 1] != b'\r';
+    if !memchr_iter(b'\n', bytes).any(lone_lf) {
+        return Cow::Borrowed(text);
+    }
+    let mut converted = String::with_capacity(text.len() + text.len() / 32);
+    let mut last = 0;
+    for i in memchr_iter(b'\n', bytes).filter(|&i| lone_lf(i)) {
+        converted.push_str(&text[last..i]);
+        converted.push_str("\r\n");
+        last = i + 1;
+    }
+    converted.push_str(&text[last..]);
+    Cow::Owned(converted)
+}
+
+/// Whether the first line ending of `text` is CRLF; `None` without any line ending
+pub fn first_line_ending_is_crlf(text: &[u8]) -> Option<bool> {
+    memchr(b'\n', text).map(|i| i > 0 && text[i - 1] == b'\r')
+}
+
+/// Marks around a replacement whose line endings `restore_line_endings` puts back: the text it
+/// replaces, then the replacement. They are noncharacters, which text is not expected to contain.
+const MATCH_START: char = '\u{FDD0}';
+const REPLACEMENT_START: char = '\u{FDD1}';
+const REPLACEMENT_END: char = '\u{FDD2}';
+/// The UTF-8 encoding the marks start with, before a last byte of 0x90 to 0x92
+const MARK_PREFIX: [u8; 2] = [0xEF, 0xB7];
+
+/// `replacement` marked for `restore_line_endings`, along with the `matched` text it replaces
+pub(crate) fn mark_replacement(matched: &str, replacement: &str) -> String {
+    format!("{MATCH_START}{matched}{REPLACEMENT_START}{replacement}{REPLACEMENT_END}")
+}
+
+/// Text with every CRLF turned into LF, and whether each of its line endings was a CRLF;
+/// `None` when it has no CRLF
+pub(crate) fn normalize_recording(text: &str) -> Option<(String, VecDeque<bool>)> {
+    let bytes = text.as_bytes();
+    let endings: VecDeque<bool> = memchr_iter(b'\n', bytes).map(|i| i > 0 && bytes[i - 1] == b'\r').collect();
+    if !endings.contains(&true) {
+        return None;
+    }
+    Some((text.replace("\r\n", "\n"), endings))
+}
+
+/// Put back the line endings `normalize_recording` turned into LF, in text with marked replacements
+///
+/// Line endings outside replacements take their own style from `endings` again, while those of
+/// the replaced text are dropped with it. A line ending of a replacement takes the style of the
+/// first line ending of the text it replaces, or else the first one of `endings`.
+pub(crate) fn restore_line_endings(text: &str, mut endings: VecDeque<bool>) -> String {
+    let first_crlf = endings.front().copied();
+    let mut restored = Vec::with_capacity(text.len() + endings.len());
+    Restorer::default().restore(text.as_bytes(), &mut endings, first_crlf, true, &mut restored);
+    String::from_utf8(restored).expect("only whole characters are removed or added")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Region {
+    #[default]
+    Text,
+    Match,
+    Replacement,
+}
+
+/// Where restoring line endings is at in marked text, from one piece of it to the next
+#[derive(Debug, Default)]
+struct Restorer {
+    region: Region,
+    /// Whether the first line ending of the text the current replacement replaces was a CRLF
+    match_crlf: Option<bool>,
+}
+
+impl Restorer {
+    /// Write `text` into `out` without its marks and with its line endings restored, as
+    /// `restore_line_endings` does
+    ///
+    /// Returns how much of `text` was restored. Unless `complete`, that stops before the start of
+    /// a mark or a CR at the end of `text`, and before a line ending of a replacement whose style
+    /// is not known yet.
+    fn restore(
+        &mut self,
+        text: &[u8],
+        endings: &mut VecDeque<bool>,
+        first_crlf: Option<bool>,
+        complete: bool,
+        out: &mut Vec<u8>
+    ) -> usize {
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            if rest.len() >= 3 && rest[..2] == MARK_PREFIX && (0x90..=0x92).contains(&rest[2]) {
+                self.region = match rest[2] {
+                    0x90 => {
+                        self.match_crlf = None;
+                        Region::Match
+                    }
+                    0x91 => Region::Replacement,
+                    _ => Region::Text,
+                };
+                i += 3;
+                continue;
+            }
+            if !complete && rest.len() < 3 && MARK_PREFIX.starts_with(rest) {
+                return i;
+            }
+            match (self.region, rest[0]) {
+                (Region::Text, b'\n') => push_line_ending(out, endings.pop_front().unwrap_or(false)),
+                (Region::Match, b'\n') => {
+                    let crlf = endings.pop_front().unwrap_or(false);
+                    self.match_crlf.get_or_insert(crlf);
+                }
+                (Region::Match, _) => {}
+                (Region::Replacement, b'\n' | b'\r') => {
+                    let len = if rest.starts_with(b"\r\n") { 2 } else { 1 };
+                    if rest[0] == b'\r' && len == 1 {
+                        if rest.len() == 1 && !complete {
+                            return i;
+                        }
+                        out.push(b'\r');
+                        i += 1;
+                        continue;
+                    }
+                    match self.match_crlf.or(first_crlf) {
+                        Some(crlf) => push_line_ending(out, crlf),
+                        None if complete => push_line_ending(out, false),
+                        None => return i,
+                    }
+                    i += len;
+                    continue;
+                }
+                (_, byte) => out.push(byte),
+            }
+            i += 1;
+        }
+        text.len()
+    }
+}
+
+fn push_line_ending(out: &mut Vec<u8>, crlf: bool) {
+    out.extend_from_slice(if crlf { b"\r\n" } else { b"\n" });
+}
+
+/// The line endings a `NormalizingReader` turned into LF, for a `LineEndingWriter` to put back
+#[derive(Debug, Default)]
+pub struct LineEndings {
+    /// Whether the first line ending read was a CRLF
+    first_crlf: Cell<Option<bool>>,
+    /// Whether each line ending read and not yet written back was a CRLF
+    unwritten: RefCell<VecDeque<bool>>,
+}
+
+impl LineEndings {
+    fn record(&self, crlf: bool) {
+        if self.first_crlf.get().is_none() {
+            self.first_crlf.set(Some(crlf));
+        }
+        self.unwritten.borrow_mut().push_back(crlf);
+    }
+}
+
+/// A reader turning CRLF into LF as it is read, for the matching of `--eol-agnostic`
+///
+/// A CR ending one read waits for the next, so a CRLF split across reads is still seen whole.
+/// Each line ending read is recorded in `endings` if given, for a `LineEndingWriter` to put back.
+pub struct NormalizingReader<'a, R> {
+    inner: R,
+    raw: Vec<u8>,
+    normalized: Vec<u8>,
+    normalized_start: usize,
+    /// A CR read last, whose next byte is not read yet
+    pending_cr: bool,
+    eof: bool,
+    endings: Option<&'a LineEndings>,
+}
+
+impl<'a, R: io::Read> NormalizingReader<'a, R> {
+    pub fn new(inner: R, buffer_size: usize, endings: Option<&'a LineEndings>) -> Self {
+        NormalizingReader {
+            inner,
+            raw: vec![0; buffer_size.max(1)],
+            normalized: Vec::new(),
+            normalized_start: 0,
+            pending_cr: false,
+            eof: false,
+            endings,
+        }
+    }
+
+    fn fill_normalized(&mut self) -> io::Result<()> {
+        let n = self.inner.read(&mut self.raw)?;
+        self.eof = n == 0;
+        self.normalized.clear();
+        self.normalized_start = 0;
+        let mut after_cr = std::mem::take(&mut self.pending_cr);
+        for &byte in &self.raw[..n] {
+            if after_cr && byte != b'\n' {
+                self.normalized.push(b'\r');
+            }
+            if byte == b'\n' && let Some(endings) = self.endings {
+                endings.record(after_cr);
+            }
+            after_cr = byte == b'\r';
+            if !after_cr {
+                self.normalized.push(byte);
+            }
+        }
+        // A CR at the very end stands alone
+        if after_cr && self.eof {
+            self.normalized.push(b'\r');
+        } else {
+            self.pending_cr = after_cr;
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for NormalizingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.normalized_start == self.normalized.len() {
+            if self.eof || buf.is_empty() {
+                return Ok(0);
+            }
+            self.fill_normalized()?;
+        }
+        let n = buf.len().min(self.normalized.len() - self.normalized_start);
+        buf[..n].copy_from_slice(&self.normalized[self.normalized_start..self.normalized_start + n]);
+        self.normalized_start += n;
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Style<'a> {
+    /// Every line ending as CRLF (`true`) or LF
+    Fixed(bool),
+    /// Line endings put back as a `NormalizingReader` recorded them, in marked text
+    Restored(&'a LineEndings),
+}
+
+/// A writer setting the style of line endings as they are written
+///
+/// Either every line ending is turned into CRLF or LF, or the text has marked replacements and
+/// the line endings a `NormalizingReader` read are put back as `restore_line_endings` does. Call
+/// `finish` once everything is written.
+pub struct LineEndingWriter<'a, W> {
+    inner: W,
+    style: Style<'a>,
+    /// A CR written last, held back in case a LF follows
+    pending_cr: bool,
+    /// The end of what was written, held back until its line endings can be restored
+    held: Vec<u8>,
+    restorer: Restorer,
+    converted: Vec<u8>,
+}
+
+impl<'a, W: io::Write> LineEndingWriter<'a, W> {
+    /// A writer turning every line ending into CRLF (`crlf`) or LF
+    pub fn converting(inner: W, crlf: bool) -> Self {
+        Self::new(inner, Style::Fixed(crlf))
+    }
+
+    /// A writer putting back the line endings recorded in `endings`
+    pub fn restoring(inner: W, endings: &'a LineEndings) -> Self {
+        Self::new(inner, Style::Restored(endings))
+    }
+
+    fn new(inner: W, style: Style<'a>) -> Self {
+        LineEndingWriter {
+            inner,
+            style,
+            pending_cr: false,
+            held: Vec::new(),
+            restorer: Restorer::default(),
+            converted: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Style::Restored(endings) = self.style {
+            self.write_restored(endings, &[], true)?;
+        }
+        if std::mem::take(&mut self.pending_cr) {
+            self.inner.write_all(b"\r")?;
+        }
+        self.inner.flush()
+    }
+
+    fn write_restored(&mut self, endings: &LineEndings, buf: &[u8], complete: bool) -> io::Result<()> {
+        let mut held = std::mem::take(&mut self.held);
+        held.extend_from_slice(buf);
+        self.converted.clear();
+        let restored = self.restorer.restore(
+            &held,
+            &mut endings.unwritten.borrow_mut(),
+            endings.first_crlf.get(),
+            complete,
+            &mut self.converted
+        );
+        held.drain(..restored);
+        self.held = held;
+        self.inner.write_all(&self.converted)
+    }
+}
+
+impl<W: io::Write> io::Write for LineEndingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let crlf = match self.style {
+            Style::Fixed(crlf) => crlf,
+            Style::Restored(endings) => {
+                self.write_restored(endings, buf, false)?;
+                return Ok(buf.len());
+            }
+        };
+        self.converted.clear();
+        if std::mem::take(&mut self.pending_cr) {
+            self.converted.push(b'\r');
+        }
+        for &byte in buf {
+            if byte == b'\n' {
+                let after_cr = self.converted.last() == Some(&b'\r');
+                if crlf && !after_cr {
+                    self.converted.push(b'\r');
+                } else if !crlf && after_cr {
+                    self.converted.pop();
+                }
+            }
+            self.converted.push(byte);
+        }
+        if self.converted.last() == Some(&b'\r') {
+            self.converted.pop();
+            self.pending_cr = true;
+        }
+        self.inner.write_all(&self.converted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_convert_line_endings() {
+        assert_eq!(normalize_line_endings("a\r\nb\nc\rd"), "a\nb\nc\rd");
+        assert_eq!(convert_line_endings("a\r\nb\nc\rd\n", true), "a\r\nb\r\nc\rd\r\n");
+        assert_eq!(convert_line_endings("a\r\nb\n", false), "a\nb\n");
+        assert!(matches!(convert_line_endings("a\r\nb", true), Cow::Borrowed(_)));
+        assert_eq!(first_line_ending_is_crlf(b"a\r\nb\n"), Some(true));
+        assert_eq!(first_line_ending_is_crlf(b"a"), None);
+    }
+
+    #[test]
+    fn test_streaming_split_crlf() {
+        let content = b"one\r\ntwo\nthree\r\r\n\r";
+        for step in [1, 2, 3, 5] {
+            let endings = LineEndings::default();
+            let mut normalized = Vec::new();
+            NormalizingReader::new(content.as_slice(), step, Some(&endings)).read_to_end(&mut normalized).unwrap();
+            assert_eq!(normalized, b"one\ntwo\nthree\r\n\r", "step {step}");
+            assert_eq!(endings.first_crlf.get(), Some(true));
+            assert_eq!(*endings.unwritten.borrow(), [true, false, true]);
+
+            for (crlf, expected) in [(true, &b"one\r\ntwo\r\nthree\r\n\r"[..]), (false, b"one\ntwo\nthree\n\r")] {
+                let mut writer = LineEndingWriter::converting(Vec::new(), crlf);
+                for piece in normalized.chunks(step) {
+                    writer.write_all(piece).unwrap();
+                }
+                writer.finish().unwrap();
+                assert_eq!(writer.inner, expected, "step {step}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_line_endings() {
+        let content = "one\r\ntwo\nthree\r\nfour\n";
+        let (text, endings) = normalize_recording(content).unwrap();
+        assert_eq!(text, "one\ntwo\nthree\nfour\n");
+        // Unmarked, the text comes back as it was
+        assert_eq!(restore_line_endings(&text, endings.clone()), content);
+
+        // "two\nthree" (LF) becomes "2\n3", and "four" becomes "4\n"
+        let marked = format!(
+            "one\n{}\n{}\n",
+            mark_replacement("two\nthree", "2\n3"),
+            mark_replacement("four", "4\n")
+        );
+        assert_eq!(restore_line_endings(&marked, endings), "one\r\n2\n3\r\n4\r\n\n");
+        assert_eq!(normalize_recording("one\ntwo\r"), None);
+    }
+
+    #[test]
+    fn test_streaming_restore() {
+        let content = b"zero one\r\ntwo\nthree\r\n";
+        let first = mark_replacement("zero", "0\n");
+        let rest = format!(" one\n{}\n{}\n", mark_replacement("two", "2\r\n"), mark_replacement("three", ""));
+        let expected = b"0\r\n one\r\n2\r\n\n\r\n";
+        for step in [1, 2, 3, 4, 7, 64] {
+            let endings = LineEndings::default();
+            let mut reader = NormalizingReader::new(content.as_slice(), step, Some(&endings));
+            let mut writer = LineEndingWriter::restoring(Vec::new(), &endings);
+            // The replacement of "zero" is written before the first line ending is read
+            for piece in first.as_bytes().chunks(step) {
+                writer.write_all(piece).unwrap();
+            }
+            assert_eq!(writer.inner, b"0", "step {step}");
+            reader.read_to_end(&mut Vec::new()).unwrap();
+            for piece in rest.as_bytes().chunks(step) {
+                writer.write_all(piece).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_eq!(writer.inner, expected, "step {step}");
+        }
+
+        // Without any line ending read, those of replacements are LF
+        let endings = LineEndings::default();
+        let mut writer = LineEndingWriter::restoring(Vec::new(), &endings);
+        writer.write_all(mark_replacement("a", "b\nc").as_bytes()).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(writer.inner, b"b\nc");
+    }
+}
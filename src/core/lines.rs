@@ -0,0 +1,232 @@
+use std::io;
+use memchr::{memchr, memchr_iter, memrchr};
+use regex::Regex;
+use crate::core::specialized::Match;
+
+/// Which lines replacements may happen on, like a sed address
+///
+/// Lines are numbered from 1 and selected by an inclusive range of numbers and, optionally, by
+/// whether they match or do not match a regex, tested against the line without its terminator.
+/// Matches are found in the whole content as usual; a match is replaced only if every line it
+/// touches is selected.
+#[derive(Debug, Clone)]
+pub struct LineScope {
+    first: usize,
+    last: usize,
+    matching: Option<Regex>,
+    not_matching: Option<Regex>,
+}
+
+impl Default for LineScope {
+    fn default() -> Self {
+        LineScope { first: 1, last: usize::MAX, matching: None, not_matching: None }
+    }
+}
+
+impl LineScope {
+    /// Every line
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Lines `first` to `last`, inclusive
+    pub fn range(first: usize, last: usize) -> Self {
+        LineScope { first, last, ..Self::default() }
+    }
+
+    /// Parse a range like `10:200`, `10:` (to the end), `:200` (from the start) or `10` (one line)
+    pub fn parse(spec: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid line range '{}': expected N, N:M, N: or :M with numbers from 1", spec)
+        );
+        let number = |text: &str| match text.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(invalid()),
+        };
+
+        let (first, last) = match spec.split_once(':') {
+            Some((first, last)) => (
+                if first.trim().is_empty() { 1 } else { number(first)? },
+                if last.trim().is_empty() { usize::MAX } else { number(last)? },
+            ),
+            None => {
+                let n = number(spec)?;
+                (n, n)
+            }
+        };
+        if first > last {
+            return Err(invalid());
+        }
+        Ok(Self::range(first, last))
+    }
+
+    /// Only select lines this regex matches
+    pub fn with_matching(mut self, regex: Option<Regex>) -> Self {
+        self.matching = regex;
+        self
+    }
+
+    /// Only select lines this regex does not match
+    pub fn with_not_matching(mut self, regex: Option<Regex>) -> Self {
+        self.not_matching = regex;
+        self
+    }
+
+    /// Number of the last line that can be selected
+    pub fn last_line(&self) -> usize {
+        self.last
+    }
+
+    /// Whether line `number`, whose text is `line`, is selected
+    pub fn selects(&self, number: usize, line: &str) -> bool {
+        self.selects_number(number) && self.selects_text(line)
+    }
+
+    fn selects_number(&self, number: usize) -> bool {
+        (self.first..=self.last).contains(&number)
+    }
+
+    fn selects_text(&self, line: &str) -> bool {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        self.matching.as_ref().is_none_or(|regex| regex.is_match(line))
+            && !self.not_matching.as_ref().is_some_and(|regex| regex.is_match(line))
+    }
+
+    /// Whether selecting a line depends on its text, which must then be read in full
+    pub(crate) fn tests_text(&self) -> bool {
+        self.matching.is_some() || self.not_matching.is_some()
+    }
+
+    /// Keep the matches whose lines are all selected
+    pub fn select(&self, content: &str, matches: Vec<Match>) -> Vec<Match> {
+        let mut lines = LineTracker::new(self);
+        matches.into_iter()
+            .filter(|m| lines.selects(content.as_bytes(), m.start, m.end, true) == Some(true))
+            .collect()
+    }
+}
+
+/// Line numbers and line selection for positions in a buffer that slides over the input
+///
+/// Positions are looked up in increasing order between two drains, so newlines are only counted
+/// once.
+pub(crate) struct LineTracker<'s> {
+    scope: &'s LineScope,
+    /// Number of the line the buffer starts in
+    first_line: usize,
+    /// Last position looked up and the number of its line
+    cursor: (usize, usize),
+    /// Whether the last line whose text was tested is selected
+    tested: Option<(usize, bool)>,
+}
+
+impl<'s> LineTracker<'s> {
+    pub(crate) fn new(scope: &'s LineScope) -> Self {
+        LineTracker { scope, first_line: 1, cursor: (0, 1), tested: None }
+    }
+
+    fn line_at(&mut self, text: &[u8], pos: usize) -> usize {
+        let (from, line) = if pos >= self.cursor.0 { self.cursor } else { (0, self.first_line) };
+        let line = line + memchr_iter(b'\n', &text[from..pos]).count();
+        self.cursor = (pos, line);
+        line
+    }
+
+    /// Whether the match at `start..end` of `text` is on selected lines, or `None` when a line's
+    /// text must be tested but does not end within `text` yet. With `complete`, `text` runs to
+    /// the end of the input.
+    pub(crate) fn selects(&mut self, text: &[u8], start: usize, end: usize, complete: bool) -> Option<bool> {
+        let mut line = self.line_at(text, start);
+        // The last byte of the match, whose line is the last one it touches
+        let last = end.saturating_sub(1).max(start);
+        let mut line_start = if self.scope.tests_text() { self.line_start(text, start) } else { start };
+
+        loop {
+            if !self.scope.selects_number(line) {
+                return Some(false);
+            }
+            let line_end = memchr(b'\n', &text[line_start..]).map(|pos| line_start + pos);
+            if self.scope.tests_text() {
+                let selected = match self.tested {
+                    Some((tested, selected)) if tested == line => selected,
+                    _ => {
+                        let end = line_end.or(complete.then_some(text.len()))?;
+                        let selected = self.scope.selects_text(&String::from_utf8_lossy(&text[line_start..end]));
+                        self.tested = Some((line, selected));
+                        selected
+                    }
+                };
+                if !selected {
+                    return Some(false);
+                }
+            }
+            match line_end {
+                Some(line_end) if line_end < last => {
+                    line += 1;
+                    line_start = line_end + 1;
+                }
+                _ => return Some(true),
+            }
+        }
+    }
+
+    /// Start of the line containing `pos`; the buffer must hold it
+    pub(crate) fn line_start(&self, text: &[u8], pos: usize) -> usize {
+        memrchr(b'\n', &text[..pos]).map_or(0, |newline| newline + 1)
+    }
+
+    /// Where the buffer may be drained up to while keeping `pos`: the start of its line when line
+    /// text is tested, `pos` otherwise
+    pub(crate) fn keep_from(&self, text: &[u8], pos: usize) -> usize {
+        if self.scope.tests_text() { self.line_start(text, pos) } else { pos }
+    }
+
+    /// Whether no line from the one containing `pos` on can be selected
+    pub(crate) fn is_past(&mut self, text: &[u8], pos: usize) -> bool {
+        self.scope.last < usize::MAX && self.line_at(text, pos) > self.scope.last
+    }
+
+    /// The first `count` bytes of the buffer are dropped
+    pub(crate) fn drain(&mut self, text: &[u8], count: usize) {
+        self.first_line = self.line_at(text, count);
+        self.cursor = (0, self.first_line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let scope = LineScope::parse("2:4").unwrap();
+        let selected: Vec<usize> = (1..=6).filter(|&n| scope.selects(n, "")).collect();
+        assert_eq!(selected, [2, 3, 4]);
+        assert!(LineScope::parse("3:").unwrap().selects(1000, ""));
+        assert!(LineScope::parse(":3").unwrap().selects(1, ""));
+        assert!(!LineScope::parse("3").unwrap().selects(4, ""));
+
+        for spec in ["0", "4:2", "a", "1:2:3", ""] {
+            assert!(LineScope::parse(spec).is_err(), "{spec:?}");
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let content = "[a]\nkey = 1\n[b]\nkey = 2\r\nkey = 3\n";
+        let matches = crate::core::find_matches(content, "key", false);
+        let starts = |scope: &LineScope| -> Vec<usize> {
+            scope.select(content, matches.clone()).iter().map(|m| m.start).collect()
+        };
+
+        assert_eq!(starts(&LineScope::parse("3:").unwrap()), [16, 25]);
+        let not_three = LineScope::all().with_not_matching(Some(Regex::new("3$").unwrap()));
+        assert_eq!(starts(&not_three), [4, 16]);
+
+        // A match spanning lines needs all of them selected
+        let spanning = crate::core::find_matches(content, "1\n[b]", false);
+        assert_eq!(LineScope::parse("2:3").unwrap().select(content, spanning.clone()).len(), 1);
+        assert!(LineScope::parse("3:4").unwrap().select(content, spanning).is_empty());
+    }
+}
@@ -2,12 +2,62 @@ pub mod sequential;
 pub mod parallel;
 pub mod specialized;
 pub mod config;
+pub mod regexp;
+pub mod multi;
+pub mod case;
+pub mod preserve_case;
+pub mod word;
+pub mod occurrences;
+pub mod lines;
+pub mod bytes;
+pub mod eol;
 
 pub use sequential::perform_replacement;
-pub use parallel::perform_replacement_parallel;
+pub use parallel::{
+    find_matches_parallel,
+    perform_replacement_parallel,
+    perform_replacement_parallel_ignore_case,
+    perform_word_replacement_parallel
+};
+pub use case::{CaseSensitivity, fold_char, fold_str, perform_replacement_ignore_case};
 pub use specialized::{
+    Match,
+    find_matches,
+    replace_matches,
     perform_replacement_utf8_optimized, 
+    perform_configured_replacement,
+    perform_streaming_configured_replacement,
+    find_configured_matches,
+    find_selected_matches,
     perform_multi_pattern_replacement,
-    perform_streaming_replacement
+    perform_streaming_replacement,
+    perform_streaming_replacement_ignore_case,
+    perform_streaming_word_replacement
+};
+pub use preserve_case::CasePreserver;
+pub use word::{WordBoundary, find_word_matches, perform_word_replacement};
+pub use occurrences::Occurrences;
+pub use lines::LineScope;
+pub use bytes::{display_escaped, escape_bytes, perform_configured_byte_replacement, unescape_bytes};
+pub use eol::{convert_line_endings, normalize_line_endings};
+pub use config::{BackupMode, BomMode, EolMode, ReplacementConfig, ReportFormat};
+pub use regexp::{
+    build_regex,
+    build_regex_with_case,
+    build_configured_regex,
+    find_regex_matches_parallel,
+    perform_regex_replacement,
+    perform_regex_replacement_parallel,
+    perform_streaming_regex_replacement
+};
+pub use multi::{
+    MatchKind,
+    PatternSet,
+    build_pattern_set,
+    build_case_preserving_pattern_set,
+    find_pattern_set_matches,
+    find_pattern_set_matches_parallel,
+    perform_pattern_set_replacement,
+    perform_pattern_set_replacement_parallel,
+    perform_streaming_pattern_set_replacement
 };
-pub use config::ReplacementConfig;
@@ -0,0 +1,481 @@
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, Input};
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::io;
+use crate::core::case::{FoldedText, fold_str};
+use crate::core::eol::mark_replacement;
+use crate::core::parallel::chunk_ranges;
+use crate::core::preserve_case::CasePreserver;
+use crate::core::specialized::{Match, MatchSelection, perform_streaming_matcher_replacement, replace_matches};
+use crate::core::word::WordBoundary;
+
+/// How matches are chosen when several patterns match at overlapping positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchKind {
+    /// The leftmost match wins; among patterns matching there, the one listed first
+    #[default]
+    LeftmostFirst,
+    /// The leftmost match wins; among patterns matching there, the longest one
+    LeftmostLongest,
+    /// Classic Aho-Corasick: the match that ends first wins
+    Standard,
+}
+
+/// A set of `from → to` pairs compiled into a single automaton, applied in one pass
+#[derive(Debug, Clone)]
+pub struct PatternSet {
+    automaton: AhoCorasick,
+    /// Patterns of the automaton, case-folded when ignoring case
+    patterns: Vec<String>,
+    kind: MatchKind,
+    /// Replacement of each pattern of the automaton
+    replacements: Vec<String>,
+    /// When preserving case, what adapts the replacement of each pattern of the automaton to a match
+    preservers: Option<Vec<CasePreserver>>,
+    pairs: usize,
+    max_pattern_len: usize,
+    ignore_case: bool,
+    word_boundary: Option<WordBoundary>,
+    /// Overlapping automaton, to look for whole-word alternatives where the preferred match isn't one
+    overlapping: Option<AhoCorasick>,
+    /// Whether replacements are marked for `restore_line_endings`
+    marking: bool,
+}
+
+impl PatternSet {
+    /// Number of `from → to` pairs in the set
+    pub fn len(&self) -> usize {
+        self.pairs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs == 0
+    }
+
+    /// Only replace whole words, delimited by the boundary's word characters
+    ///
+    /// Where the match the match kind prefers is not bounded, another pattern matching at the
+    /// same position may be, and is then chosen by the same rule; otherwise the search moves on.
+    pub fn with_word_boundary(mut self, boundary: Option<WordBoundary>) -> io::Result<Self> {
+        self.overlapping = match &boundary {
+            Some(_) => Some(
+                AhoCorasickBuilder::new()
+                    .ascii_case_insensitive(self.ignore_case)
+                    .build(&self.patterns)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            ),
+            None => None,
+        };
+        self.word_boundary = boundary;
+        Ok(self)
+    }
+
+    /// The same set, with each replacement marked for `restore_line_endings` along with the text it replaces
+    pub(crate) fn marking_replacements(&self) -> Self {
+        PatternSet { marking: true, ..self.clone() }
+    }
+
+    /// The replacement for a match of the automaton's pattern `index`
+    pub fn replacement_for(&self, index: usize, matched: &str) -> Cow<'_, str> {
+        let replacement = match &self.preservers {
+            Some(preservers) => preservers[index].replacement_for(matched),
+            None => Cow::Borrowed(self.replacements[index].as_str()),
+        };
+        if self.marking {
+            Cow::Owned(mark_replacement(matched, &replacement))
+        } else {
+            replacement
+        }
+    }
+
+    fn replacement_bytes_for(&self, index: usize, matched: &[u8]) -> Cow<'_, [u8]> {
+        let replacement = match std::str::from_utf8(matched) {
+            Ok(matched) => self.replacement_for(index, matched),
+            Err(_) if self.marking => {
+                Cow::Owned(mark_replacement(&String::from_utf8_lossy(matched), &self.replacements[index]))
+            }
+            Err(_) => Cow::Borrowed(self.replacements[index].as_str()),
+        };
+        match replacement {
+            Cow::Borrowed(replacement) => Cow::Borrowed(replacement.as_bytes()),
+            Cow::Owned(replacement) => Cow::Owned(replacement.into_bytes()),
+        }
+    }
+}
+
+/// Compile `from → to` pairs into a pattern set, rejecting empty patterns as `InvalidInput`
+///
+/// With `ignore_case`, patterns are case-folded and matched against case-folded content, since the
+/// automaton's own case insensitivity only covers ASCII.
+pub fn build_pattern_set(pairs: &[(String, String)], kind: MatchKind, ignore_case: bool) -> io::Result<PatternSet> {
+    if let Some(index) = pairs.iter().position(|(pattern, _)| pattern.is_empty()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("pattern #{} is empty", index + 1)
+        ));
+    }
+
+    let patterns: Vec<String> = pairs.iter().map(|(pattern, _)| pattern.clone()).collect();
+    let replacements = pairs.iter().map(|(_, replacement)| replacement.clone()).collect();
+    compile_pattern_set(patterns, replacements, None, pairs.len(), kind, ignore_case)
+}
+
+/// Compile `from → to` pairs into a pattern set that preserves the case of each match
+///
+/// Patterns are matched case-insensitively, and each replacement is adapted to the casing of the
+/// match by a `CasePreserver`; patterns made of several words are also searched in the other
+/// identifier conventions, so `foo_bar → baz_qux` turns `FooBar` into `BazQux`.
+pub fn build_case_preserving_pattern_set(pairs: &[(String, String)], kind: MatchKind) -> io::Result<PatternSet> {
+    if let Some(index) = pairs.iter().position(|(pattern, _)| pattern.is_empty()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("pattern #{} is empty", index + 1)
+        ));
+    }
+
+    let mut patterns = Vec::new();
+    let mut replacements = Vec::new();
+    let mut preservers = Vec::new();
+    for (pattern, replacement) in pairs {
+        let preserver = CasePreserver::new(pattern, replacement);
+        for search_pattern in preserver.search_patterns() {
+            patterns.push(search_pattern);
+            replacements.push(replacement.clone());
+            preservers.push(preserver.clone());
+        }
+    }
+    compile_pattern_set(patterns, replacements, Some(preservers), pairs.len(), kind, true)
+}
+
+fn compile_pattern_set(
+    patterns: Vec<String>,
+    replacements: Vec<String>,
+    preservers: Option<Vec<CasePreserver>>,
+    pairs: usize,
+    kind: MatchKind,
+    ignore_case: bool
+) -> io::Result<PatternSet> {
+    let patterns: Vec<String> = if ignore_case {
+        patterns.iter().map(|pattern| fold_str(pattern)).collect()
+    } else {
+        patterns
+    };
+
+    // Folded patterns also match ASCII content as is, which then needs no folded copy
+    let automaton = AhoCorasickBuilder::new()
+        .ascii_case_insensitive(ignore_case)
+        .match_kind(match kind {
+            MatchKind::LeftmostFirst => aho_corasick::MatchKind::LeftmostFirst,
+            MatchKind::LeftmostLongest => aho_corasick::MatchKind::LeftmostLongest,
+            MatchKind::Standard => aho_corasick::MatchKind::Standard,
+        })
+        .build(&patterns)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    Ok(PatternSet {
+        automaton,
+        max_pattern_len: patterns.iter().map(String::len).max().unwrap_or(0),
+        patterns,
+        kind,
+        replacements,
+        preservers,
+        pairs,
+        ignore_case,
+        word_boundary: None,
+        overlapping: None,
+        marking: false,
+    })
+}
+
+/// Find the matches of a single pass of the set over the content, from left to right
+///
+/// `Match::pattern` is the index of the automaton's pattern, which is only the index of the pair
+/// for sets built with `build_pattern_set`.
+pub fn find_pattern_set_matches(content: &str, set: &PatternSet) -> Vec<Match> {
+    if set.ignore_case && !content.is_ascii() {
+        let folded = FoldedText::new(content.as_bytes());
+        let matches = scan_set_matches(folded.as_bytes(), set, 0, folded.as_bytes().len());
+        return original_matches(&folded, matches);
+    }
+
+    scan_set_matches(content.as_bytes(), set, 0, content.len())
+}
+
+/// Replace every pattern of the set with its replacement in a single pass
+pub fn perform_pattern_set_replacement(content: &str, set: &PatternSet) -> (String, usize) {
+    assemble_set_output(content, set, &find_pattern_set_matches(content, set))
+}
+
+/// Parallel pattern set replacement for large files
+///
+/// Chunks are searched independently, letting matches run past the chunk end, then stitched in
+/// order the same way as regex chunks: a chunk a match spilled into is rescanned from the end of
+/// that match, which yields exactly the matches of a single sequential pass.
+pub fn perform_pattern_set_replacement_parallel(content: &str, set: &PatternSet, chunk_size: usize) -> (String, usize) {
+    assemble_set_output(content, set, &find_pattern_set_matches_parallel(content, set, chunk_size))
+}
+
+/// Find the matches of the set in parallel, in content order and exactly those of a sequential pass
+pub fn find_pattern_set_matches_parallel(content: &str, set: &PatternSet, chunk_size: usize) -> Vec<Match> {
+    if content.len() < chunk_size || chunk_size == 0 {
+        return find_pattern_set_matches(content, set);
+    }
+
+    let folded = (set.ignore_case && !content.is_ascii()).then(|| FoldedText::new(content.as_bytes()));
+    let bytes = folded.as_ref().map_or(content.as_bytes(), FoldedText::as_bytes);
+    // Folding valid UTF-8 yields valid UTF-8
+    let ranges = chunk_ranges(std::str::from_utf8(bytes).unwrap(), chunk_size);
+    let chunk_matches: Vec<Vec<Match>> = ranges
+        .par_iter()
+        .map(|&(start, end)| scan_set_matches(bytes, set, start, end))
+        .collect();
+
+    let mut matches: Vec<Match> = Vec::new();
+    for (&(start, end), chunk) in ranges.iter().zip(chunk_matches) {
+        match matches.last().map(|m| m.end) {
+            Some(last_end) if last_end > start => {
+                if last_end < end {
+                    matches.extend(scan_set_matches(bytes, set, last_end, end));
+                }
+            }
+            _ => matches.extend(chunk),
+        }
+    }
+
+    match &folded {
+        Some(folded) => original_matches(folded, matches),
+        None => matches,
+    }
+}
+
+/// Streaming pattern set replacement for very large inputs
+///
+/// Like the single pattern streaming replacement, matching is done on raw bytes. A match is only
+/// final once the longest pattern fits between its start and the end of the buffered data, since
+/// a longer or higher priority match starting at or before it could otherwise still complete.
+pub fn perform_streaming_pattern_set_replacement(
+    input: &mut impl io::Read,
+    output: &mut impl io::Write,
+    set: &PatternSet,
+    buffer_size: usize
+) -> io::Result<usize> {
+    stream_pattern_set_replacement(input, output, set, MatchSelection::default(), buffer_size)
+}
+
+/// Streaming pattern set replacement where only the matches `selection` picks are replaced
+pub(crate) fn stream_pattern_set_replacement(
+    input: &mut impl io::Read,
+    output: &mut impl io::Write,
+    set: &PatternSet,
+    selection: MatchSelection,
+    buffer_size: usize
+) -> io::Result<usize> {
+    let max_len = match set.word_boundary {
+        // Room for the character following a match
+        Some(_) => set.max_pattern_len + 4,
+        None => set.max_pattern_len,
+    };
+    perform_streaming_matcher_replacement(
+        input,
+        output,
+        buffer_size,
+        max_len,
+        set.ignore_case,
+        selection,
+        |text, from| scan_set_matches(text, set, from, text.len())
+            .into_iter()
+            .map(|m| (m.start, m.end, m.pattern))
+            .collect(),
+        |index, matched| set.replacement_bytes_for(index, matched)
+    )
+}
+
+/// Find the matches of a sequential pass started at `from`, restricted to those starting before `until`
+fn scan_set_matches(content: &[u8], set: &PatternSet, from: usize, until: usize) -> Vec<Match> {
+    let to_match = |m: aho_corasick::Match| Match { start: m.start(), end: m.end(), pattern: m.pattern().as_usize() };
+    let (Some(boundary), Some(overlapping)) = (&set.word_boundary, &set.overlapping) else {
+        return set.automaton
+            .find_iter(Input::new(content).range(from..))
+            .take_while(|m| m.start() < until)
+            .map(to_match)
+            .collect();
+    };
+
+    let bounded = |m: &aho_corasick::Match| boundary.is_bounded(content, m.start(), m.end());
+    let mut matches = Vec::new();
+    let mut pos = from;
+
+    if set.kind == MatchKind::Standard {
+        // Overlapping matches come by end, so the first bounded one is the one that ends first
+        while let Some(m) = overlapping.find_overlapping_iter(Input::new(content).range(pos..)).find(bounded) {
+            if m.start() >= until {
+                break;
+            }
+            matches.push(to_match(m));
+            pos = m.end();
+        }
+        return matches;
+    }
+
+    while let Some(m) = set.automaton.find(Input::new(content).range(pos..)) {
+        if m.start() >= until {
+            break;
+        }
+        if bounded(&m) {
+            matches.push(to_match(m));
+            pos = m.end();
+            continue;
+        }
+
+        // Every match starting here lies within the length of the longest pattern
+        let window = m.start()..(m.start() + set.max_pattern_len).min(content.len());
+        let alternatives = overlapping
+            .find_overlapping_iter(Input::new(content).range(window))
+            .filter(|alternative| alternative.start() == m.start() && bounded(alternative));
+        let preferred = match set.kind {
+            MatchKind::LeftmostLongest => alternatives.min_by_key(|alternative| (usize::MAX - alternative.len(), alternative.pattern())),
+            _ => alternatives.min_by_key(|alternative| alternative.pattern()),
+        };
+        match preferred {
+            Some(preferred) => {
+                matches.push(to_match(preferred));
+                pos = preferred.end();
+            }
+            None => pos = m.start() + 1,
+        }
+    }
+    matches
+}
+
+fn original_matches(folded: &FoldedText, matches: Vec<Match>) -> Vec<Match> {
+    matches.into_iter()
+        .map(|m| Match {
+            start: folded.original_offset(m.start),
+            end: folded.original_offset(m.end),
+            pattern: m.pattern,
+        })
+        .collect()
+}
+
+fn assemble_set_output(content: &str, set: &PatternSet, matches: &[Match]) -> (String, usize) {
+    replace_matches(content, matches, |m| set.replacement_for(m.pattern, m.as_str(content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::word::WordBoundary;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn test_match_kinds() {
+        let pairs = pairs(&[("abc", "1"), ("abcd", "2"), ("bcd", "3")]);
+        let cases = [
+            (MatchKind::LeftmostFirst, "1d"),
+            (MatchKind::LeftmostLongest, "2"),
+            (MatchKind::Standard, "1d"),
+        ];
+        for (kind, expected) in cases {
+            let set = build_pattern_set(&pairs, kind, false).unwrap();
+            assert_eq!(perform_pattern_set_replacement("abcd", &set), (expected.to_string(), 1), "{kind:?}");
+        }
+
+        let set = build_pattern_set(&self::pairs(&[("abcd", "x"), ("bc", "y")]), MatchKind::Standard, false).unwrap();
+        assert_eq!(perform_pattern_set_replacement("abcd", &set).0, "ayd");
+    }
+
+    #[test]
+    fn test_swap_in_single_pass() {
+        let set = build_pattern_set(&pairs(&[("cat", "dog"), ("dog", "cat")]), MatchKind::default(), false).unwrap();
+        assert_eq!(perform_pattern_set_replacement("cat chases dog", &set), ("dog chases cat".to_string(), 2));
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let pairs = pairs(&[("straße", "street"), ("kelvin", "K"), ("Été", "summer")]);
+        let set = build_pattern_set(&pairs, MatchKind::default(), true).unwrap();
+        let content = "STRASSE Straße \u{212A}ELVIN été ÉTÉ ".repeat(30);
+        let expected = ("STRASSE street K summer summer ".repeat(30), 120);
+        assert_eq!(perform_pattern_set_replacement(&content, &set), expected);
+        assert_eq!(perform_pattern_set_replacement("KELVIN kelvin", &set), ("K K".to_string(), 2));
+
+        for chunk_size in [1, 5, 64] {
+            assert_eq!(perform_pattern_set_replacement_parallel(&content, &set, chunk_size), expected);
+        }
+        for buffer_size in [1, 3, 64] {
+            let mut output = Vec::new();
+            let count = perform_streaming_pattern_set_replacement(&mut content.as_bytes(), &mut output, &set, buffer_size).unwrap();
+            assert_eq!((String::from_utf8(output).unwrap(), count), expected);
+        }
+    }
+
+    #[test]
+    fn test_case_preserving_set() {
+        let pairs = pairs(&[("foo_bar", "baz_qux"), ("été", "hiver")]);
+        let set = build_case_preserving_pattern_set(&pairs, MatchKind::default()).unwrap();
+        assert_eq!(set.len(), 2);
+
+        let content = "foo_bar FooBar fooBar FOO_BAR foo-bar Été ÉTÉ été ".repeat(20);
+        let expected = ("baz_qux BazQux bazQux BAZ_QUX baz-qux Hiver HIVER hiver ".repeat(20), 160);
+        assert_eq!(perform_pattern_set_replacement(&content, &set), expected);
+        for chunk_size in [1, 7, 64] {
+            assert_eq!(perform_pattern_set_replacement_parallel(&content, &set, chunk_size), expected);
+        }
+        for buffer_size in [1, 3, 64] {
+            let mut output = Vec::new();
+            let count = perform_streaming_pattern_set_replacement(&mut content.as_bytes(), &mut output, &set, buffer_size).unwrap();
+            assert_eq!((String::from_utf8(output).unwrap(), count), expected);
+        }
+    }
+
+    #[test]
+    fn test_whole_words() {
+        let pairs = pairs(&[("id", "key"), ("idx", "index"), ("width", "w")]);
+        let content = "id idx width valid idxs id-x (idx) ".repeat(20);
+        let expected = ("key index w valid idxs key-x (index) ".repeat(20), 100);
+
+        for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest, MatchKind::Standard] {
+            let set = build_pattern_set(&pairs, kind, false).unwrap()
+                .with_word_boundary(Some(WordBoundary::unicode())).unwrap();
+            assert_eq!(perform_pattern_set_replacement(&content, &set), expected, "{kind:?}");
+            for chunk_size in [1, 7, 64] {
+                assert_eq!(perform_pattern_set_replacement_parallel(&content, &set, chunk_size), expected, "{kind:?}");
+            }
+            for buffer_size in [1, 3, 64] {
+                let mut output = Vec::new();
+                let count = perform_streaming_pattern_set_replacement(&mut content.as_bytes(), &mut output, &set, buffer_size).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_pattern_rejected() {
+        let err = build_pattern_set(&pairs(&[("a", "b"), ("", "c")]), MatchKind::default(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_parallel_and_streaming_match_sequential() {
+        let content = "abcd abc bcd héllo abcdabc ".repeat(40);
+        let pairs = pairs(&[("abc", "1"), ("abcd", "2"), ("bcd", "3"), ("é", "e"), ("o abc", "4"), ("d", "")]);
+
+        for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest, MatchKind::Standard] {
+            let set = build_pattern_set(&pairs, kind, false).unwrap();
+            let expected = perform_pattern_set_replacement(&content, &set);
+
+            for chunk_size in [1, 2, 3, 7, 64, 1000] {
+                let parallel = perform_pattern_set_replacement_parallel(&content, &set, chunk_size);
+                assert_eq!(parallel, expected, "{kind:?}, chunk size {chunk_size}");
+            }
+            for buffer_size in [1, 2, 5, 16, 4096] {
+                let mut output = Vec::new();
+                let count = perform_streaming_pattern_set_replacement(&mut content.as_bytes(), &mut output, &set, buffer_size).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "{kind:?}, buffer size {buffer_size}");
+            }
+        }
+    }
+}
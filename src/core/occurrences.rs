@@ -0,0 +1,138 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Which matches get replaced, by occurrence number (the first match is number 1)
+///
+/// Matches are numbered in content order, after every other constraint such as whole words. The
+/// numbering restarts with each file unless it is global, in which case it runs on across the
+/// files of a run, in the order they are processed.
+#[derive(Debug, Clone)]
+pub struct Occurrences {
+    /// Inclusive ranges of selected numbers
+    ranges: Vec<(usize, usize)>,
+    global: bool,
+    /// Numbers handed out so far
+    counter: Arc<AtomicUsize>,
+}
+
+impl Occurrences {
+    fn from_ranges(ranges: Vec<(usize, usize)>) -> Self {
+        Occurrences { ranges, global: false, counter: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// The first `count` matches
+    pub fn first(count: usize) -> Self {
+        Self::from_ranges(vec![(1, count)])
+    }
+
+    /// Only match number `n`
+    pub fn nth(n: usize) -> Self {
+        Self::from_ranges(vec![(n, n)])
+    }
+
+    /// Parse a list like `2..5,8,10..`: single numbers and inclusive ranges, possibly open-ended
+    pub fn parse(spec: &str) -> io::Result<Self> {
+        let invalid = |item: &str| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid occurrence '{}': expected N, N..M, N.. or ..M with numbers from 1", item)
+        );
+        let number = |text: &str, item: &str| match text.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(invalid(item)),
+        };
+
+        let mut ranges = Vec::new();
+        for item in spec.split(',') {
+            let range = match item.split_once("..") {
+                Some((first, last)) => (
+                    if first.trim().is_empty() { 1 } else { number(first, item)? },
+                    if last.trim().is_empty() { usize::MAX } else { number(last, item)? },
+                ),
+                None => {
+                    let n = number(item, item)?;
+                    (n, n)
+                }
+            };
+            if range.0 > range.1 {
+                return Err(invalid(item));
+            }
+            ranges.push(range);
+        }
+        Ok(Self::from_ranges(ranges))
+    }
+
+    /// Number matches across all the files of a run instead of per file
+    pub fn with_global_numbering(mut self, global: bool) -> Self {
+        self.global = global;
+        self
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.global
+    }
+
+    /// The selection to use for the next file: the same numbering when global, a fresh one otherwise
+    pub fn for_file(&self) -> Self {
+        if self.global {
+            self.clone()
+        } else {
+            Self::from_ranges(self.ranges.clone())
+        }
+    }
+
+    pub fn contains(&self, number: usize) -> bool {
+        self.ranges.iter().any(|&(first, last)| (first..=last).contains(&number))
+    }
+
+    /// Hand out numbers to the next `count` matches, returning the number of the first one
+    pub fn next_numbers(&self, count: usize) -> usize {
+        self.counter.fetch_add(count, Ordering::SeqCst) + 1
+    }
+
+    /// Whether no match still to be numbered can be selected
+    pub fn is_exhausted(&self) -> bool {
+        let last = self.ranges.iter().map(|&(_, last)| last).max().unwrap_or(0);
+        self.counter.load(Ordering::SeqCst) >= last
+    }
+
+    /// Number the given matches, in order, and keep the selected ones
+    pub fn select<T>(&self, matches: Vec<T>) -> Vec<T> {
+        let first = self.next_numbers(matches.len());
+        matches.into_iter()
+            .enumerate()
+            .filter(|(index, _)| self.contains(first + index))
+            .map(|(_, m)| m)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let occurrences = Occurrences::parse("2..4, 8,10..").unwrap();
+        let selected: Vec<usize> = (1..=12).filter(|&n| occurrences.contains(n)).collect();
+        assert_eq!(selected, [2, 3, 4, 8, 10, 11, 12]);
+        assert!(Occurrences::parse("..2").unwrap().contains(1));
+
+        for spec in ["0", "3..2", "a", "1,,2", ""] {
+            assert!(Occurrences::parse(spec).is_err(), "{spec:?}");
+        }
+    }
+
+    #[test]
+    fn test_numbering_scope() {
+        let occurrences = Occurrences::parse("2,4").unwrap();
+        assert_eq!(occurrences.select(vec!['a', 'b', 'c']), ['b']);
+        assert_eq!(occurrences.select(vec!['d', 'e']), ['d']);
+        assert!(occurrences.is_exhausted());
+        assert_eq!(occurrences.for_file().select(vec!['a', 'b']), ['b']);
+
+        let global = Occurrences::first(3).with_global_numbering(true);
+        assert_eq!(global.for_file().select(vec![1, 2]), [1, 2]);
+        assert_eq!(global.for_file().select(vec![3, 4]), [3]);
+    }
+}
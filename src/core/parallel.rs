@@ -1,86 +1,238 @@
 use rayon::prelude::*;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use crate::core::sequential::{perform_replacement_ascii, perform_replacement};
+use memchr::memmem;
+use crate::core::case::{FoldedText, fold_str, perform_replacement_ignore_case};
+use crate::core::sequential::perform_replacement;
+use crate::core::specialized::Match;
+use crate::core::word::{WordBoundary, perform_word_replacement};
 
 /// Parallel replacement for large files
+///
+/// Produces exactly the output of `perform_replacement` whatever the chunk size: matches are
+/// located in parallel, including those straddling chunk boundaries, resolved leftmost-first,
+/// and the output is then assembled chunk by chunk in parallel.
 pub fn perform_replacement_parallel(content: &str, pattern: &str, replacement: &str, chunk_size: usize) -> (String, usize) {
-    if content.len() < chunk_size {
+    if content.len() < chunk_size || pattern.is_empty() {
         return perform_replacement(content, pattern, replacement);
     }
     
-    // For ASCII content, use byte-based parallel processing
-    if content.is_ascii() && pattern.is_ascii() && replacement.is_ascii() {
-        return perform_replacement_parallel_ascii(content.as_bytes(), pattern.as_bytes(), replacement.as_bytes(), chunk_size);
+    let ranges = chunk_ranges(content, chunk_size);
+    let starts = find_match_positions_parallel(content.as_bytes(), pattern.as_bytes(), &ranges);
+    let spans: Vec<(usize, usize)> = starts.iter().map(|&start| (start, start + pattern.len())).collect();
+    let result = assemble_parallel(content.as_bytes(), &spans, replacement.as_bytes(), &ranges);
+    
+    // Matches and chunks both sit on character boundaries, so the output is valid UTF-8
+    (String::from_utf8(result).unwrap(), spans.len())
+}
+
+/// Case-insensitive parallel replacement, producing exactly the output of `perform_replacement_ignore_case`
+///
+/// The content is case-folded once, then searched and assembled in parallel like the exact variant.
+pub fn perform_replacement_parallel_ignore_case(content: &str, pattern: &str, replacement: &str, chunk_size: usize) -> (String, usize) {
+    if content.len() < chunk_size || pattern.is_empty() {
+        return perform_replacement_ignore_case(content, pattern, replacement);
     }
     
-    // Unicode parallel processing (more complex due to UTF-8 boundaries)
-    perform_replacement_parallel_unicode(content, pattern, replacement, chunk_size)
+    let matches = find_matches_parallel(content, pattern, true, None, chunk_size);
+    let spans: Vec<(usize, usize)> = matches.iter().map(|m| (m.start, m.end)).collect();
+    let result = assemble_parallel(content.as_bytes(), &spans, replacement.as_bytes(), &chunk_ranges(content, chunk_size));
+    
+    (String::from_utf8(result).unwrap(), spans.len())
 }
 
-fn perform_replacement_parallel_unicode(content: &str, pattern: &str, replacement: &str, chunk_size: usize) -> (String, usize) {
-    let total_count = AtomicUsize::new(0);
+/// Whole-word parallel replacement, producing exactly the output of `perform_word_replacement`
+///
+/// Boundaries are checked against the whole content, so occurrences at chunk edges are judged
+/// like any other.
+pub fn perform_word_replacement_parallel(
+    content: &str,
+    pattern: &str,
+    replacement: &str,
+    ignore_case: bool,
+    boundary: &WordBoundary,
+    chunk_size: usize
+) -> (String, usize) {
+    if content.len() < chunk_size || pattern.is_empty() {
+        return perform_word_replacement(content, pattern, replacement, ignore_case, boundary);
+    }
     
-    // Find safe UTF-8 boundaries for chunking
-    let chunks: Vec<_> = find_utf8_chunks(content, chunk_size)
-        .par_iter()
-        .map(|chunk| {
-            let (result, count) = perform_replacement(chunk, pattern, replacement);
-            total_count.fetch_add(count, Ordering::Relaxed);
-            result
-        })
-        .collect();
+    let matches = find_matches_parallel(content, pattern, ignore_case, Some(boundary), chunk_size);
+    let spans: Vec<(usize, usize)> = matches.iter().map(|m| (m.start, m.end)).collect();
+    let result = assemble_parallel(content.as_bytes(), &spans, replacement.as_bytes(), &chunk_ranges(content, chunk_size));
+    
+    (String::from_utf8(result).unwrap(), spans.len())
+}
+
+/// Find the matches of a literal pattern in parallel, case-insensitively and as whole words if asked
+///
+/// The matches come in content order, exactly those of a sequential pass whatever the chunk
+/// size, so they can be numbered deterministically.
+pub fn find_matches_parallel(
+    content: &str,
+    pattern: &str,
+    ignore_case: bool,
+    boundary: Option<&WordBoundary>,
+    chunk_size: usize
+) -> Vec<Match> {
+    let folded = ignore_case.then(|| FoldedText::new(content.as_bytes()));
+    let needle = if ignore_case { fold_str(pattern) } else { pattern.to_string() };
+    let text = folded.as_ref().map_or(content.as_bytes(), FoldedText::as_bytes);
+    let original = |pos| folded.as_ref().map_or(pos, |folded| folded.original_offset(pos));
     
-    (chunks.join(""), total_count.load(Ordering::Relaxed))
+    // Folding valid UTF-8 yields valid UTF-8
+    let ranges = chunk_ranges(std::str::from_utf8(text).unwrap(), chunk_size);
+    find_filtered_match_positions(text, needle.as_bytes(), &ranges, |start| {
+        boundary.is_none_or(|boundary| boundary.is_bounded(text, start, start + needle.len()))
+    })
+        .into_iter()
+        .map(|start| Match { start: original(start), end: original(start + needle.len()), pattern: 0 })
+        .collect()
 }
 
-fn perform_replacement_parallel_ascii(content: &[u8], pattern: &[u8], replacement: &[u8], chunk_size: usize) -> (String, usize) {
-    let total_count = AtomicUsize::new(0);
+/// Find the start of every match a sequential leftmost-first scan would report
+///
+/// Each chunk is scanned in parallel from its start, skipping to the end of every match kept
+/// (looking up to `pattern.len() - 1` bytes past the chunk's end), so it holds at most one
+/// position per match. A match spilling over from the previous chunk can shift the first ones
+/// of the next: those are found again by a sequential scan from its end, which stops as soon as
+/// it meets a position the chunk already found.
+pub fn find_match_positions_parallel(content: &[u8], pattern: &[u8], ranges: &[(usize, usize)]) -> Vec<usize> {
+    find_filtered_match_positions(content, pattern, ranges, |_| true)
+}
+
+/// `find_match_positions_parallel` restricted to the occurrences `keep` accepts
+///
+/// A rejected occurrence is skipped without moving past its end, so it hides none of those
+/// overlapping it.
+pub(crate) fn find_filtered_match_positions(
+    content: &[u8],
+    pattern: &[u8],
+    ranges: &[(usize, usize)],
+    keep: impl Fn(usize) -> bool + Sync
+) -> Vec<usize> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
     
-    // Split into chunks, ensuring we don't split in the middle of potential matches
-    let safe_chunk_size = chunk_size.max(pattern.len() * 2);
-    let chunks: Vec<_> = content.chunks(safe_chunk_size)
-        .collect::<Vec<_>>()
+    let finder = memmem::Finder::new(pattern);
+    // First accepted occurrence starting in `pos..end`
+    let next_kept = |mut pos: usize, end: usize| -> Option<usize> {
+        let window_end = (end + pattern.len() - 1).min(content.len());
+        while pos < end {
+            let start = pos + finder.find(&content[pos..window_end])?;
+            if keep(start) {
+                return Some(start);
+            }
+            pos = start + 1;
+        }
+        None
+    };
+    
+    let found: Vec<Vec<usize>> = ranges
         .par_iter()
-        .map(|chunk| {
-            let (result, count) = perform_replacement_ascii(chunk, pattern, replacement);
-            total_count.fetch_add(count, Ordering::Relaxed);
-            result
+        .map(|&(start, end)| {
+            let mut found = Vec::new();
+            let mut pos = start;
+            while let Some(start) = next_kept(pos, end) {
+                found.push(start);
+                pos = start + pattern.len();
+            }
+            found
         })
         .collect();
     
-    let combined: Vec<u8> = chunks.into_iter().flatten().collect();
-    let total_replacements = total_count.load(Ordering::Relaxed);
-    
-    // Safe conversion since we know all content is valid ASCII/UTF-8
-    (String::from_utf8(combined).unwrap(), total_replacements)
+    let mut starts = Vec::new();
+    let mut last_end = 0;
+    for (&(_, end), found) in ranges.iter().zip(&found) {
+        let mut resync = 0;
+        if found.first().is_some_and(|&first| first < last_end) {
+            resync = found.len();
+            let mut pos = last_end;
+            while let Some(start) = next_kept(pos, end) {
+                if let Ok(index) = found.binary_search(&start) {
+                    resync = index;
+                    break;
+                }
+                starts.push(start);
+                pos = start + pattern.len();
+            }
+        }
+        starts.extend_from_slice(&found[resync..]);
+        if let Some(&start) = starts.last() {
+            last_end = start + pattern.len();
+        }
+    }
+    starts
 }
 
-fn find_utf8_chunks(content: &str, chunk_size: usize) -> Vec<&str> {
-    let mut chunks = Vec::new();
+/// Split content into chunks of roughly `chunk_size` bytes on UTF-8 character boundaries
+pub fn chunk_ranges(content: &str, chunk_size: usize) -> Vec<(usize, usize)> {
+    let chunk_size = chunk_size.max(1);
+    let mut ranges = Vec::new();
     let mut start = 0;
     
     while start < content.len() {
-        let end = (start + chunk_size).min(content.len());
-        
-        // Find a safe UTF-8 boundary (character boundary)
-        let safe_end = if end < content.len() {
-            content.ceil_char_boundary(end)
-        } else {
-            end
-        };
-        
-        chunks.push(&content[start..safe_end]);
-        start = safe_end;
+        let end = content.ceil_char_boundary((start + chunk_size).min(content.len()));
+        ranges.push((start, end));
+        start = end;
+    }
+    
+    ranges
+}
+
+/// Replace the given sorted, non-overlapping spans, building each chunk's output in parallel
+///
+/// A match belongs to the chunk it starts in, so a chunk's output begins after the end of any
+/// match spilling over from earlier chunks.
+fn assemble_parallel(content: &[u8], spans: &[(usize, usize)], replacement: &[u8], ranges: &[(usize, usize)]) -> Vec<u8> {
+    let mut regions = Vec::with_capacity(ranges.len());
+    let mut span_index = 0;
+    let mut region_start = 0;
+    
+    for &(start, end) in ranges {
+        region_start = region_start.max(start);
+        let first_span = span_index;
+        while span_index < spans.len() && spans[span_index].0 < end {
+            span_index += 1;
+        }
+        regions.push((region_start, &spans[first_span..span_index]));
+        if let Some(&(_, span_end)) = spans[first_span..span_index].last() {
+            region_start = region_start.max(span_end);
+        }
     }
     
-    chunks
+    let pieces: Vec<Vec<u8>> = regions
+        .par_iter()
+        .enumerate()
+        .map(|(index, &(region_start, region_spans))| {
+            let region_end = regions.get(index + 1).map_or(content.len(), |next| next.0);
+            let mut piece = Vec::with_capacity(region_end.saturating_sub(region_start));
+            let mut last_end = region_start;
+            for &(start, end) in region_spans {
+                piece.extend_from_slice(&content[last_end..start]);
+                piece.extend_from_slice(replacement);
+                last_end = end;
+            }
+            if last_end < region_end {
+                piece.extend_from_slice(&content[last_end..region_end]);
+            }
+            piece
+        })
+        .collect();
+    
+    let mut result = Vec::with_capacity(pieces.iter().map(Vec::len).sum());
+    for piece in pieces {
+        result.extend_from_slice(&piece);
+    }
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     use crate::core::sequential::perform_replacement;
+    use crate::core::word::{WordBoundary, perform_word_replacement};
 
     #[test]
     fn test_parallel_ascii_replacement() {
@@ -107,10 +259,98 @@ mod tests {
         let pattern = "world";
         let replacement = "Rust";
         
-        let (result_parallel, count_parallel) = perform_replacement_parallel(&content, pattern, replacement, 1024);
-        let (result_sequential, count_sequential) = perform_replacement(&content, pattern, replacement);
+        let (result_parallel, count_parallel) = perform_replacement_parallel(content, pattern, replacement, 1024);
+        let (result_sequential, count_sequential) = perform_replacement(content, pattern, replacement);
         
         assert_eq!(result_parallel, result_sequential);
         assert_eq!(count_parallel, count_sequential);
     }
+
+    #[test]
+    fn test_parallel_matches_across_chunk_boundaries() {
+        let content = "aaaaaaa bcbcbc abcabc";
+        for chunk_size in 1..content.len() {
+            for pattern in ["aa", "aaa", "bcb", "cab", "abcabc", "c a"] {
+                assert_eq!(
+                    perform_replacement_parallel(content, pattern, "<>", chunk_size),
+                    perform_replacement(content, pattern, "<>"),
+                    "pattern {pattern:?}, chunk size {chunk_size}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_overlaps_across_chunk_edge() {
+        let content = "a".repeat(11);
+        let sequential = |pattern: &str| -> Vec<usize> {
+            content.match_indices(pattern).map(|(start, _)| start).collect()
+        };
+        assert_eq!(find_match_positions_parallel(b"aaaaa", b"aa", &chunk_ranges("aaaaa", 3)), [0, 2]);
+        for chunk_size in 1..content.len() {
+            let ranges = chunk_ranges(&content, chunk_size);
+            for pattern in ["aa", "aaa", "aaaa"] {
+                assert_eq!(
+                    find_match_positions_parallel(content.as_bytes(), pattern.as_bytes(), &ranges),
+                    sequential(pattern),
+                    "pattern {pattern:?}, chunk size {chunk_size}"
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_parallel_equals_sequential_ascii(
+            content in "[ab ]{0,300}",
+            pattern in "[ab ]{1,6}",
+            replacement in "[xy]{0,4}",
+            chunk_size in 1usize..64
+        ) {
+            prop_assert_eq!(
+                perform_replacement_parallel(&content, &pattern, &replacement, chunk_size),
+                perform_replacement(&content, &pattern, &replacement)
+            );
+        }
+
+        #[test]
+        fn prop_parallel_equals_sequential_unicode(
+            content in "[aé€😀 ]{0,200}",
+            pattern in "[aé€😀 ]{1,4}",
+            replacement in "[xü]{0,3}",
+            chunk_size in 1usize..64
+        ) {
+            prop_assert_eq!(
+                perform_replacement_parallel(&content, &pattern, &replacement, chunk_size),
+                perform_replacement(&content, &pattern, &replacement)
+            );
+        }
+
+        #[test]
+        fn prop_parallel_equals_sequential_ignore_case(
+            content in "[aAsSſkK\u{212A}éÉ ]{0,200}",
+            pattern in "[aAsSſkK\u{212A}éÉ ]{1,4}",
+            replacement in "[xü]{0,3}",
+            chunk_size in 1usize..64
+        ) {
+            prop_assert_eq!(
+                perform_replacement_parallel_ignore_case(&content, &pattern, &replacement, chunk_size),
+                perform_replacement_ignore_case(&content, &pattern, &replacement)
+            );
+        }
+
+        #[test]
+        fn prop_parallel_equals_sequential_word(
+            content in "[abAé -]{0,200}",
+            pattern in "[abé -]{1,3}",
+            ignore_case in any::<bool>(),
+            chunk_size in 1usize..64
+        ) {
+            let boundary = WordBoundary::unicode();
+            prop_assert_eq!(
+                perform_word_replacement_parallel(&content, &pattern, "x", ignore_case, &boundary, chunk_size),
+                perform_word_replacement(&content, &pattern, "x", ignore_case, &boundary)
+            );
+        }
+    }
 }
@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+
+/// Casing of one word of a match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Lower,
+    Upper,
+    Capitalized,
+    /// No cased letters, or a mix that is none of the above: the replacement word is kept as given
+    Mixed,
+}
+
+/// Adapts a replacement to the casing of each match of its pattern
+///
+/// The pattern and the replacement are given in the same convention, e.g. `foo_bar → baz_qux`.
+/// A match spelled exactly like the pattern gets the replacement as given. Otherwise both are
+/// split into words (at `_`, `-`, spaces and case changes) and the replacement words take the
+/// casing of the matched words and the separator of the match, so `FooBar` becomes `BazQux` and
+/// `FOO-BAR` becomes `BAZ-QUX`. A single word pattern passes its casing to the whole replacement.
+#[derive(Debug, Clone)]
+pub struct CasePreserver {
+    pattern: String,
+    replacement: String,
+    /// Length in characters of each word of the pattern, which case folding keeps in a match
+    word_lengths: Vec<usize>,
+    replacement_words: Vec<String>,
+}
+
+impl CasePreserver {
+    pub fn new(pattern: &str, replacement: &str) -> Self {
+        CasePreserver {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            word_lengths: split_words(pattern).iter().map(|word| word.chars().count()).collect(),
+            replacement_words: split_words(replacement).iter().map(|word| word.to_string()).collect(),
+        }
+    }
+
+    /// The spellings of the pattern to search for case-insensitively: the pattern itself, and for
+    /// a pattern of several words, its words joined by each separator and by nothing
+    pub fn search_patterns(&self) -> Vec<String> {
+        let mut patterns = vec![self.pattern.clone()];
+        if self.word_lengths.len() > 1 {
+            let words: Vec<String> = split_words(&self.pattern).iter().map(|word| word.to_lowercase()).collect();
+            for separator in ["_", "-", " ", ""] {
+                let joined = words.join(separator);
+                if !patterns.iter().any(|pattern| pattern.to_lowercase() == joined) {
+                    patterns.push(joined);
+                }
+            }
+        }
+        patterns
+    }
+
+    /// The replacement for one match of a search pattern
+    pub fn replacement_for(&self, matched: &str) -> Cow<'_, str> {
+        if matched == self.pattern {
+            return Cow::Borrowed(&self.replacement);
+        }
+
+        if self.word_lengths.len() < 2 {
+            return match shape_of(matched) {
+                Shape::Lower => Cow::Owned(self.replacement.to_lowercase()),
+                Shape::Upper => Cow::Owned(self.replacement.to_uppercase()),
+                Shape::Capitalized => Cow::Owned(capitalize_first(&self.replacement)),
+                Shape::Mixed => Cow::Borrowed(&self.replacement),
+            };
+        }
+
+        let mut words = Vec::with_capacity(self.word_lengths.len());
+        let mut separator = None;
+        let mut rest = matched;
+        for (index, &length) in self.word_lengths.iter().enumerate() {
+            if index > 0 && let Some(c) = rest.chars().next().filter(|&c| is_separator(c)) {
+                separator.get_or_insert(&rest[..c.len_utf8()]);
+                rest = &rest[c.len_utf8()..];
+            }
+            let end = rest.char_indices().nth(length).map_or(rest.len(), |(pos, _)| pos);
+            words.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+
+        let mut shapes: Vec<Shape> = words.iter().map(|word| shape_of(word)).collect();
+        // A one-letter word, like the `X` of `XAxis` or `X_AXIS`, is capitalized unless all the
+        // other words are uppercase
+        if words.iter().zip(&shapes).filter(|(word, _)| !is_single_capital(word)).all(|(_, &shape)| shape == Shape::Upper) {
+            for (word, shape) in words.iter().zip(shapes.iter_mut()) {
+                if is_single_capital(word) {
+                    *shape = Shape::Upper;
+                }
+            }
+        }
+
+        let last = shapes.len() - 1;
+        let rendered: Vec<String> = self.replacement_words.iter()
+            .enumerate()
+            .map(|(index, word)| apply_shape(word, shapes[index.min(last)]))
+            .collect();
+        Cow::Owned(rendered.join(separator.unwrap_or("")))
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '_' | '-' | ' ')
+}
+
+/// Split an identifier into words at separators and case changes (`fooBar`, `HTTPServer`)
+fn split_words(s: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (index, &(pos, c)) in chars.iter().enumerate() {
+        if is_separator(c) {
+            if let Some(start) = start.take() {
+                words.push(&s[start..pos]);
+            }
+            continue;
+        }
+        let Some(word_start) = start else {
+            start = Some(pos);
+            continue;
+        };
+
+        let prev = chars[index - 1].1;
+        let next = chars.get(index + 1).map(|&(_, c)| c);
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next.is_some_and(char::is_lowercase)));
+        if boundary {
+            words.push(&s[word_start..pos]);
+            start = Some(pos);
+        }
+    }
+
+    if let Some(start) = start {
+        words.push(&s[start..]);
+    }
+    words
+}
+
+fn shape_of(word: &str) -> Shape {
+    let mut cased = word.chars().filter(|c| c.is_lowercase() || c.is_uppercase());
+    let Some(first) = cased.next() else {
+        return Shape::Mixed;
+    };
+    let rest: Vec<char> = cased.collect();
+
+    if first.is_uppercase() {
+        if rest.iter().all(|c| c.is_lowercase()) {
+            Shape::Capitalized
+        } else if rest.iter().all(|c| c.is_uppercase()) {
+            Shape::Upper
+        } else {
+            Shape::Mixed
+        }
+    } else if rest.iter().all(|c| c.is_lowercase()) {
+        Shape::Lower
+    } else {
+        Shape::Mixed
+    }
+}
+
+fn is_single_capital(word: &str) -> bool {
+    let mut cased = word.chars().filter(|c| c.is_lowercase() || c.is_uppercase());
+    cased.next().is_some_and(char::is_uppercase) && cased.next().is_none()
+}
+
+fn apply_shape(word: &str, shape: Shape) -> String {
+    match shape {
+        Shape::Lower => word.to_lowercase(),
+        Shape::Upper => word.to_uppercase(),
+        Shape::Capitalized => {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.as_str().to_lowercase().chars()).collect(),
+                None => String::new(),
+            }
+        }
+        Shape::Mixed => word.to_string(),
+    }
+}
+
+/// Uppercase the first character, keeping the rest as given
+fn capitalize_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("foo_bar"), ["foo", "bar"]);
+        assert_eq!(split_words("fooBarBaz"), ["foo", "Bar", "Baz"]);
+        assert_eq!(split_words("HTTPServer-v2"), ["HTTP", "Server", "v2"]);
+        assert_eq!(split_words("XAxis"), ["X", "Axis"]);
+        assert_eq!(split_words("__foo"), ["foo"]);
+    }
+
+    #[test]
+    fn test_single_word() {
+        let preserver = CasePreserver::new("foo", "bar");
+        assert_eq!(preserver.search_patterns(), ["foo"]);
+        for (matched, expected) in [("foo", "bar"), ("Foo", "Bar"), ("FOO", "BAR"), ("fOo", "bar")] {
+            assert_eq!(preserver.replacement_for(matched), expected);
+        }
+    }
+
+    #[test]
+    fn test_identifier_conventions() {
+        let preserver = CasePreserver::new("foo_bar", "baz_qux");
+        assert_eq!(preserver.search_patterns(), ["foo_bar", "foo-bar", "foo bar", "foobar"]);
+        let cases = [
+            ("foo_bar", "baz_qux"),
+            ("FOO_BAR", "BAZ_QUX"),
+            ("FooBar", "BazQux"),
+            ("fooBar", "bazQux"),
+            ("foo-bar", "baz-qux"),
+            ("Foo Bar", "Baz Qux"),
+            ("foobar", "bazqux"),
+        ];
+        for (matched, expected) in cases {
+            assert_eq!(preserver.replacement_for(matched), expected, "{matched}");
+        }
+
+        let preserver = CasePreserver::new("x_axis", "horizontal_axis");
+        assert_eq!(preserver.replacement_for("XAxis"), "HorizontalAxis");
+        assert_eq!(preserver.replacement_for("X_AXIS"), "HORIZONTAL_AXIS");
+
+        let preserver = CasePreserver::new("userId", "accountNumber");
+        assert_eq!(preserver.replacement_for("USER_ID"), "ACCOUNT_NUMBER");
+        assert_eq!(preserver.replacement_for("UserId"), "AccountNumber");
+    }
+}
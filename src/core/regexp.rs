@@ -0,0 +1,396 @@
+use rayon::prelude::*;
+use regex::{Captures, Regex, RegexBuilder};
+use std::io;
+use crate::core::ReplacementConfig;
+use crate::core::lines::LineTracker;
+use crate::core::parallel::chunk_ranges;
+use crate::core::specialized::{Match, MatchSelection};
+
+/// A regex match with its replacement already expanded (empty when no expansion is needed)
+struct RegexSpan {
+    start: usize,
+    end: usize,
+    expanded: String,
+}
+
+/// Compile a pattern for regex mode, reporting syntax errors as `InvalidInput`
+pub fn build_regex(pattern: &str) -> io::Result<Regex> {
+    build_regex_with_case(pattern, false)
+}
+
+/// Compile a pattern for regex mode, optionally matching without regard to case
+pub fn build_regex_with_case(pattern: &str, ignore_case: bool) -> io::Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// The regex to match with, if the configuration is in regex mode (and has no pattern set)
+pub fn build_configured_regex(pattern: &str, config: &ReplacementConfig) -> io::Result<Option<Regex>> {
+    if !config.use_regex || config.pattern_set.is_some() {
+        return Ok(None);
+    }
+    build_regex_with_case(pattern, config.case_sensitivity.ignores_case_regex(pattern)).map(Some)
+}
+
+/// Regex replacement with capture group expansion (`$1`, `${name}`) in the replacement string
+pub fn perform_regex_replacement(content: &str, regex: &Regex, replacement: &str) -> (String, usize) {
+    let spans = scan_regex_matches(content, regex, replacement, 0, content.len(), None);
+    assemble_regex_output(content, replacement, &spans)
+}
+
+/// Parallel regex replacement for large files
+///
+/// Each chunk is searched against the whole content so anchors and word boundaries see their
+/// real context, and matches may run past the chunk end. Chunks are then stitched in order:
+/// when a match spills into the next chunk, that chunk is rescanned from the end of the match,
+/// which yields exactly the matches of a single sequential pass.
+pub fn perform_regex_replacement_parallel(content: &str, regex: &Regex, replacement: &str, chunk_size: usize) -> (String, usize) {
+    if content.len() < chunk_size || chunk_size == 0 {
+        return perform_regex_replacement(content, regex, replacement);
+    }
+
+    let spans = scan_regex_chunks(content, regex, replacement, chunk_size);
+    assemble_regex_output(content, replacement, &spans)
+}
+
+/// Streaming regex replacement for very large UTF-8 inputs
+///
+/// Matches are committed only once at least `buffer_size` bytes of lookahead are available
+/// after them, and a match running to the end of what was read waits for more input, however
+/// long it grows, so a match is never cut at a read boundary.
+pub fn perform_streaming_regex_replacement(
+    input: &mut impl io::Read,
+    output: &mut impl io::Write,
+    regex: &Regex,
+    replacement: &str,
+    buffer_size: usize
+) -> io::Result<usize> {
+    stream_regex_replacement(input, output, regex, replacement, MatchSelection::default(), buffer_size)
+}
+
+/// Find the matches of the regex, in parallel for content larger than `chunk_size`
+///
+/// The matches come in content order, exactly those of a sequential pass, so they can be
+/// numbered deterministically.
+pub fn find_regex_matches_parallel(content: &str, regex: &Regex, chunk_size: usize) -> Vec<Match> {
+    let spans = if content.len() < chunk_size || chunk_size == 0 {
+        scan_regex_matches(content, regex, "", 0, content.len(), None)
+    } else {
+        scan_regex_chunks(content, regex, "", chunk_size)
+    };
+    spans.into_iter().map(|span| Match { start: span.start, end: span.end, pattern: 0 }).collect()
+}
+
+/// Streaming regex replacement where only the matches `selection` picks are replaced
+///
+/// When lines are tested against a regex, matches are only committed once their last line has
+/// ended, and the lines they are on are kept in the buffer.
+pub(crate) fn stream_regex_replacement(
+    input: &mut impl io::Read,
+    output: &mut impl io::Write,
+    regex: &Regex,
+    replacement: &str,
+    selection: MatchSelection,
+    buffer_size: usize
+) -> io::Result<usize> {
+    let mut lines = selection.lines.map(LineTracker::new);
+    let window = buffer_size.max(1);
+    let mut buffer = vec![0u8; window];
+    // Raw bytes not yet decoded (an incomplete UTF-8 sequence at a read boundary)
+    let mut raw_tail: Vec<u8> = Vec::new();
+    // Text still to be processed, preceded by `context_len` bytes of already written context
+    let mut pending = String::new();
+    let mut context_len = 0;
+    let mut last_end: Option<usize> = None;
+    let mut total_count = 0;
+
+    loop {
+        let bytes_read = input.read(&mut buffer)?;
+        let at_eof = bytes_read == 0;
+        raw_tail.extend_from_slice(&buffer[..bytes_read]);
+
+        // Move every complete UTF-8 sequence into `pending`
+        let valid_up_to = match std::str::from_utf8(&raw_tail) {
+            Ok(text) => text.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        pending.push_str(std::str::from_utf8(&raw_tail[..valid_up_to]).unwrap());
+        raw_tail.drain(..valid_up_to);
+
+        // Invalid bytes cannot be searched: flush the text before them and copy them through
+        let invalid_len = std::str::from_utf8(&raw_tail).err().and_then(|e| e.error_len());
+
+        if at_eof || invalid_len.is_some() {
+            let spans = scan_regex_matches(&pending, regex, replacement, context_len, pending.len(), last_end);
+            let decisions = line_decisions(&spans, &pending, true, lines.as_mut());
+            let spans = select_spans(spans, &decisions, selection);
+            total_count += write_regex_output(output, &pending, context_len, pending.len(), replacement, &spans)?;
+            if let Some(lines) = &mut lines {
+                lines.drain(pending.as_bytes(), pending.len());
+            }
+            pending.clear();
+            context_len = 0;
+            last_end = None;
+
+            match invalid_len {
+                Some(invalid_len) => {
+                    output.write_all(&raw_tail[..invalid_len])?;
+                    raw_tail.drain(..invalid_len);
+                    continue;
+                }
+                None => {
+                    // Only a truncated UTF-8 sequence can be left at end of input
+                    output.write_all(&raw_tail)?;
+                    break;
+                }
+            }
+        }
+
+        if pending.len() <= context_len + window {
+            continue;
+        }
+
+        let limit = pending.floor_char_boundary(pending.len() - window);
+        let mut spans = scan_regex_matches(&pending, regex, replacement, context_len, limit, last_end);
+        // A match running to the end of what was read may go on in the next read, so it is left
+        // for then, the buffer growing until the match ends
+        let open = spans.last().filter(|span| span.end == pending.len()).map(|span| span.start);
+        if open.is_some() {
+            spans.pop();
+        }
+        let decisions = line_decisions(&spans, &pending, false, lines.as_mut());
+        // A match whose lines have not ended yet is left, with those after it, for the next read
+        let deferred = spans.get(decisions.len()).map(|span| span.start).or(open);
+        spans.truncate(decisions.len());
+        let commit = deferred.unwrap_or_else(|| spans.last().map_or(limit, |span| span.end.max(limit)));
+        // Keep the last written character as look-behind context for `^`, `$` and `\b`
+        let mut keep_from = pending.floor_char_boundary(commit.saturating_sub(1));
+        if let Some(lines) = &lines {
+            keep_from = keep_from.min(lines.keep_from(pending.as_bytes(), commit));
+        }
+        last_end = spans.last()
+            .filter(|span| span.end == commit)
+            .map(|_| commit - keep_from);
+        total_count += write_regex_output(output, &pending, context_len, commit, replacement, &select_spans(spans, &decisions, selection))?;
+
+        if selection.is_done(lines.as_mut(), pending.as_bytes(), commit) {
+            output.write_all(&pending.as_bytes()[commit..])?;
+            output.write_all(&raw_tail)?;
+            io::copy(input, output)?;
+            break;
+        }
+
+        context_len = commit - keep_from;
+        if let Some(lines) = &mut lines {
+            lines.drain(pending.as_bytes(), keep_from);
+        }
+        pending.drain(..keep_from);
+    }
+
+    output.flush()?;
+    Ok(total_count)
+}
+
+/// Whether each span is on selected lines, stopping before the first span whose lines have not
+/// ended yet unless `complete`
+fn line_decisions(spans: &[RegexSpan], text: &str, complete: bool, lines: Option<&mut LineTracker>) -> Vec<bool> {
+    let Some(lines) = lines else {
+        return vec![true; spans.len()];
+    };
+    spans.iter()
+        .map_while(|span| lines.selects(text.as_bytes(), span.start, span.end, complete))
+        .collect()
+}
+
+/// Keep the spans on selected lines, then among them the selected occurrences
+fn select_spans(spans: Vec<RegexSpan>, decisions: &[bool], selection: MatchSelection) -> Vec<RegexSpan> {
+    let spans: Vec<RegexSpan> = spans.into_iter()
+        .zip(decisions)
+        .filter(|&(_, &selected)| selected)
+        .map(|(span, _)| span)
+        .collect();
+    match selection.occurrences {
+        Some(occurrences) => occurrences.select(spans),
+        None => spans,
+    }
+}
+
+/// Scan chunks in parallel, letting matches run past the chunk end, then stitch them in order:
+/// a chunk a match spilled into is rescanned from the end of that match
+fn scan_regex_chunks(content: &str, regex: &Regex, replacement: &str, chunk_size: usize) -> Vec<RegexSpan> {
+    let ranges = chunk_ranges(content, chunk_size);
+    let chunk_spans: Vec<Vec<RegexSpan>> = ranges
+        .par_iter()
+        .map(|&(start, end)| scan_regex_matches(content, regex, replacement, start, end, None))
+        .collect();
+
+    let mut spans: Vec<RegexSpan> = Vec::new();
+    for (&(start, end), chunk) in ranges.iter().zip(chunk_spans) {
+        match spans.last().map(|span| span.end) {
+            Some(last_end) if last_end >= start => {
+                if last_end < end {
+                    spans.extend(scan_regex_matches(content, regex, replacement, last_end, end, Some(last_end)));
+                }
+            }
+            _ => spans.extend(chunk),
+        }
+    }
+    spans
+}
+
+/// Find the matches a sequential `captures_iter` pass would yield, restricted to those starting
+/// in `from..until`. `last_end` is the end of the previous match, if it ended exactly at `from`.
+fn scan_regex_matches(
+    content: &str,
+    regex: &Regex,
+    replacement: &str,
+    from: usize,
+    until: usize,
+    mut last_end: Option<usize>
+) -> Vec<RegexSpan> {
+    let expand = replacement.contains('$');
+    let mut spans = Vec::new();
+    let mut at = from;
+    // An empty match may still start at the very end of the content
+    let until = if until == content.len() { until + 1 } else { until };
+
+    while at <= content.len() {
+        let Some(caps) = regex.captures_at(content, at) else { break };
+        let m = caps.get(0).unwrap();
+        if m.start() >= until {
+            break;
+        }
+
+        // Like the regex iterators, never report an empty match right where the previous match ended
+        if m.is_empty() && Some(m.end()) == last_end {
+            match content[at..].chars().next() {
+                Some(c) => at += c.len_utf8(),
+                None => break,
+            }
+            continue;
+        }
+
+        spans.push(RegexSpan {
+            start: m.start(),
+            end: m.end(),
+            expanded: if expand { expand_captures(&caps, replacement) } else { String::new() },
+        });
+        last_end = Some(m.end());
+        at = m.end();
+    }
+
+    spans
+}
+
+fn expand_captures(caps: &Captures, replacement: &str) -> String {
+    let mut expanded = String::new();
+    caps.expand(replacement, &mut expanded);
+    expanded
+}
+
+fn assemble_regex_output(content: &str, replacement: &str, spans: &[RegexSpan]) -> (String, usize) {
+    let mut result = Vec::with_capacity(content.len());
+    // Writing into a Vec<u8> cannot fail
+    let count = write_regex_output(&mut result, content, 0, content.len(), replacement, spans).unwrap();
+    (String::from_utf8(result).unwrap(), count)
+}
+
+fn write_regex_output(
+    output: &mut impl io::Write,
+    content: &str,
+    from: usize,
+    to: usize,
+    replacement: &str,
+    spans: &[RegexSpan]
+) -> io::Result<usize> {
+    let expand = replacement.contains('$');
+    let mut last_end = from;
+
+    for span in spans {
+        output.write_all(&content.as_bytes()[last_end..span.start])?;
+        let text = if expand { span.expanded.as_str() } else { replacement };
+        output.write_all(text.as_bytes())?;
+        last_end = span.end;
+    }
+
+    output.write_all(&content.as_bytes()[last_end..to])?;
+    Ok(spans.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequential_reference(content: &str, regex: &Regex, replacement: &str) -> (String, usize) {
+        let count = regex.find_iter(content).count();
+        (regex.replace_all(content, replacement).into_owned(), count)
+    }
+
+    #[test]
+    fn test_capture_groups() {
+        let regex = build_regex(r"(\w+)@(?<domain>\w+)\.com").unwrap();
+        let (result, count) = perform_regex_replacement("alice@example.com, bob@test.com", &regex, "${domain}:$1");
+        assert_eq!(result, "example:alice, test:bob");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let err = build_regex("(unclosed").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let content = "foo1 bar22 héllo333 foo4444\nbaz ".repeat(50);
+        let patterns = [r"\d+", r"\b\w", r"^\w+", r"(?m)^\w+", r"o*", r"[a-z]+\d+ [a-z]+", r"é|\s"];
+
+        for pattern in patterns {
+            let regex = build_regex(pattern).unwrap();
+            let expected = sequential_reference(&content, &regex, "<$0>");
+            for chunk_size in [1, 2, 3, 7, 64, 1000] {
+                let parallel = perform_regex_replacement_parallel(&content, &regex, "<$0>", chunk_size);
+                assert_eq!(parallel, expected, "pattern {pattern:?}, chunk size {chunk_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_matches_sequential() {
+        let content = "foo1 bar22 héllo333 foo4444\nbaz ".repeat(50);
+        let patterns = [r"\d+", r"\b\w", r"^\w+", r"(?m)^\w+", r"o*", r"é|\s", r"\w+$"];
+
+        for pattern in patterns {
+            let regex = build_regex(pattern).unwrap();
+            let expected = sequential_reference(&content, &regex, "[$0]");
+            // The lookahead window must be longer than any single match
+            for buffer_size in [16, 23, 64, 4096] {
+                let mut output = Vec::new();
+                let count = perform_streaming_regex_replacement(
+                    &mut content.as_bytes(),
+                    &mut output,
+                    &regex,
+                    "[$0]",
+                    buffer_size
+                ).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "pattern {pattern:?}, buffer size {buffer_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_matches_longer_than_buffer() {
+        let content = format!("a{} a{}b x", "b".repeat(40), "b".repeat(100));
+        for pattern in [r"a(b+)", r"b+", r"\w+", r"[ab]*"] {
+            let regex = build_regex(pattern).unwrap();
+            let expected = sequential_reference(&content, &regex, "<$1>");
+            for buffer_size in [1, 4, 16, 64] {
+                let mut output = Vec::new();
+                let count = perform_streaming_regex_replacement(&mut content.as_bytes(), &mut output, &regex, "<$1>", buffer_size).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "pattern {pattern:?}, buffer size {buffer_size}");
+            }
+        }
+    }
+}
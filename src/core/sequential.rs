@@ -46,6 +46,40 @@ pub fn perform_replacement_ascii(content: &[u8], pattern: &[u8], replacement: &[
     (result, count)
 }
 
+/// ASCII case-insensitive variant of `perform_replacement_ascii`; other bytes are compared exactly
+pub fn perform_replacement_ascii_ignore_case(content: &[u8], pattern: &[u8], replacement: &[u8]) -> (Vec<u8>, usize) {
+    if pattern.is_empty() || pattern.len() > content.len() {
+        return (content.to_vec(), 0);
+    }
+    
+    let mut result = Vec::with_capacity(content.len());
+    let mut count = 0;
+    let mut last_end = 0;
+    
+    let first = pattern[0];
+    let last_start = content.len() - pattern.len();
+    let mut pos = 0;
+    while pos <= last_start {
+        let candidates = &content[pos..=last_start];
+        let Some(offset) = memchr::memchr2(first.to_ascii_lowercase(), first.to_ascii_uppercase(), candidates) else {
+            break;
+        };
+        let start = pos + offset;
+        if content[start..start + pattern.len()].eq_ignore_ascii_case(pattern) {
+            result.extend_from_slice(&content[last_end..start]);
+            result.extend_from_slice(replacement);
+            pos = start + pattern.len();
+            last_end = pos;
+            count += 1;
+        } else {
+            pos = start + 1;
+        }
+    }
+    
+    result.extend_from_slice(&content[last_end..]);
+    (result, count)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,6 +104,17 @@ mod tests {
         assert_eq!(count, 0);
     }
 
+    #[test]
+    fn test_ascii_ignore_case() {
+        let (replaced_content, count) = perform_replacement_ascii_ignore_case(b"Foo fOO foo_bar", b"FOO", b"baz");
+        assert_eq!(replaced_content, b"baz baz baz_bar");
+        assert_eq!(count, 3);
+        
+        let (replaced_content, count) = perform_replacement_ascii_ignore_case(b"1.2 1x2", b"1.2", b"12");
+        assert_eq!(replaced_content, b"12 1x2");
+        assert_eq!(count, 1);
+    }
+
     #[test]
     fn test_empty_pattern() {
         let content = "Hello world!";
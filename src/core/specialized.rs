@@ -1,24 +1,121 @@
+use crate::core::ReplacementConfig;
+use crate::core::config::EolMode;
+use crate::core::eol::{
+    LineEndingWriter,
+    LineEndings,
+    NormalizingReader,
+    convert_line_endings,
+    mark_replacement,
+    normalize_line_endings,
+    normalize_recording,
+    restore_line_endings
+};
+use crate::core::case::{FoldedText, complete_utf8_len, fold_str, perform_replacement_ignore_case};
+use crate::core::multi::{
+    find_pattern_set_matches_parallel,
+    perform_pattern_set_replacement,
+    perform_pattern_set_replacement_parallel,
+    stream_pattern_set_replacement
+};
+use crate::core::lines::{LineScope, LineTracker};
+use crate::core::occurrences::Occurrences;
+use crate::core::parallel::{
+    find_filtered_match_positions,
+    find_matches_parallel,
+    perform_replacement_parallel,
+    perform_replacement_parallel_ignore_case,
+    perform_word_replacement_parallel
+};
+use crate::core::regexp::{
+    find_regex_matches_parallel,
+    perform_regex_replacement,
+    perform_regex_replacement_parallel,
+    stream_regex_replacement
+};
 use crate::core::sequential::{perform_replacement, perform_replacement_ascii};
+use crate::core::word::{WordBoundary, perform_word_replacement};
+use std::borrow::Cow;
+use std::sync::Arc;
 use std::collections::HashMap;
 use once_cell::sync::Lazy;
 use aho_corasick::AhoCorasick;
+use memchr::memmem;
+use regex::Regex;
 
 /// Cache for repeated operations
 static REPLACEMENT_CACHE: Lazy<std::sync::Mutex<HashMap<String, (String, usize)>>> = 
     Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
 
+/// A match in some content: its byte range, and which pattern matched (always 0 for a single pattern)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub pattern: usize,
+}
+
+impl Match {
+    /// The matched text
+    pub fn as_str<'c>(&self, content: &'c str) -> &'c str {
+        &content[self.start..self.end]
+    }
+}
+
+/// Find the non-overlapping matches of a literal pattern, from left to right
+///
+/// With `ignore_case`, matching uses Unicode simple case folding, and the offsets still refer to
+/// the original content.
+pub fn find_matches(content: &str, pattern: &str, ignore_case: bool) -> Vec<Match> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    if !ignore_case {
+        return memmem::find_iter(content.as_bytes(), pattern.as_bytes())
+            .map(|start| Match { start, end: start + pattern.len(), pattern: 0 })
+            .collect();
+    }
+
+    let folded_pattern = fold_str(pattern);
+    let folded = FoldedText::new(content.as_bytes());
+    memmem::find_iter(folded.as_bytes(), folded_pattern.as_bytes())
+        .map(|start| Match {
+            start: folded.original_offset(start),
+            end: folded.original_offset(start + folded_pattern.len()),
+            pattern: 0,
+        })
+        .collect()
+}
+
+/// Rebuild the content with each match, in order, replaced by what `replace` gives for it
+pub fn replace_matches<'r>(
+    content: &str,
+    matches: &[Match],
+    mut replace: impl FnMut(&Match) -> Cow<'r, str>
+) -> (String, usize) {
+    let mut result = String::with_capacity(content.len());
+    let mut last_end = 0;
+
+    for m in matches {
+        result.push_str(&content[last_end..m.start]);
+        result.push_str(&replace(m));
+        last_end = m.end;
+    }
+
+    result.push_str(&content[last_end..]);
+    (result, matches.len())
+}
+
 /// UTF-8 optimized replacement that chooses the best algorithm based on content characteristics
 pub fn perform_replacement_utf8_optimized(content: &str, pattern: &str, replacement: &str, use_cache: bool) -> (String, usize) {
     if use_cache {
         let cache_key = generate_cache_key(content, pattern, replacement);
-        
-        if let Ok(cache) = REPLACEMENT_CACHE.try_lock() {
-            if let Some(cached_result) = cache.get(&cache_key) {
-                return cached_result.clone();
-            }
+
+        if let Ok(cache) = REPLACEMENT_CACHE.try_lock()
+            && let Some(cached_result) = cache.get(&cache_key) {
+            return cached_result.clone();
         }
     }
-    
+
     let result = if should_use_ascii_optimization(content, pattern, replacement) {
         // Use ASCII-only optimization for better performance
         let content_bytes = content.as_bytes();
@@ -33,44 +130,302 @@ pub fn perform_replacement_utf8_optimized(content: &str, pattern: &str, replacem
         // Use Unicode-aware replacement
         perform_replacement(content, pattern, replacement)
     };
-    
-    if use_cache {
-        if let Ok(mut cache) = REPLACEMENT_CACHE.try_lock() {
-            let cache_key = generate_cache_key(content, pattern, replacement);
-            cache.insert(cache_key, result.clone());
-        }
+
+    if use_cache
+        && let Ok(mut cache) = REPLACEMENT_CACHE.try_lock() {
+        let cache_key = generate_cache_key(content, pattern, replacement);
+        cache.insert(cache_key, result.clone());
     }
-    
+
     result
 }
 
+/// In-memory replacement with the engine the configuration calls for
+///
+/// A pattern set from the configuration takes precedence, then `regex` if given, then the literal
+/// `pattern`, matched case-insensitively when the configured case sensitivity says so and as a
+/// whole word when a word boundary is configured. Content larger than the parallel threshold is
+/// processed in parallel.
+///
+/// Line endings are matched and written as `config.eol_agnostic` and `config.eol` say, so with a
+/// line ending conversion the result may differ from `content` without any replacement. Matching
+/// them agnostically while keeping them leaves each line ending outside the replaced text as it
+/// was; those of a replacement follow the text it replaces, or else the first line ending.
+pub fn perform_configured_replacement(
+    content: &str,
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> (String, usize) {
+    if let Some(crlf) = fixed_line_ending(config) {
+        let text = if config.eol_agnostic { normalize_line_endings(content) } else { Cow::Borrowed(content) };
+        let (result, count) = replace_configured(&text, pattern, replacement, regex, config);
+        return match convert_line_endings(&result, crlf) {
+            Cow::Owned(converted) => (converted, count),
+            Cow::Borrowed(_) => (result, count),
+        };
+    }
+    if config.eol_agnostic && let Some((text, endings)) = normalize_recording(content) {
+        let (replacement, config) = mark_replacements(pattern, replacement, regex, config);
+        let (result, count) = replace_configured(&text, pattern, &replacement, regex, &config);
+        return (restore_line_endings(&result, endings), count);
+    }
+    replace_configured(content, pattern, replacement, regex, config)
+}
+
+/// The line ending every one is converted to, if the configuration says so
+fn fixed_line_ending(config: &ReplacementConfig) -> Option<bool> {
+    match config.eol {
+        EolMode::Lf => Some(false),
+        EolMode::Crlf => Some(true),
+        EolMode::Keep => None,
+    }
+}
+
+/// The replacement, and the configuration with its pattern set, marking each replacement for
+/// `restore_line_endings` along with the text it replaces
+fn mark_replacements<'c>(
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    config: &'c ReplacementConfig
+) -> (String, Cow<'c, ReplacementConfig>) {
+    match (&config.pattern_set, regex) {
+        (Some(set), _) => {
+            let set = Some(Arc::new(set.marking_replacements()));
+            (replacement.to_string(), Cow::Owned(config.clone().with_pattern_set(set)))
+        }
+        // The whole match, expanded like the rest of the replacement
+        (None, Some(_)) => (mark_replacement("${0}", replacement), Cow::Borrowed(config)),
+        (None, None) => (mark_replacement(pattern, replacement), Cow::Borrowed(config)),
+    }
+}
+
+fn replace_configured(
+    content: &str,
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> (String, usize) {
+    let parallel = content.len() > config.parallel_threshold;
+    let chunk_size = config.parallel_threshold / 2;
+
+    if config.occurrences.is_some() || config.line_scope.is_some() {
+        let matches = find_selected_matches(content, pattern, regex, config);
+        let expand = regex.is_some() && replacement.contains('$');
+        return replace_matches(content, &matches, |m| match (&config.pattern_set, regex) {
+            (Some(set), _) => set.replacement_for(m.pattern, m.as_str(content)),
+            (None, Some(regex)) if expand => {
+                let mut expanded = String::new();
+                // The regex matches the same text again from the start of the match
+                if let Some(caps) = regex.captures_at(content, m.start) {
+                    caps.expand(replacement, &mut expanded);
+                }
+                Cow::Owned(expanded)
+            }
+            _ => Cow::Borrowed(replacement),
+        });
+    }
+
+    if let Some(set) = &config.pattern_set {
+        if parallel {
+            perform_pattern_set_replacement_parallel(content, set, chunk_size)
+        } else {
+            perform_pattern_set_replacement(content, set)
+        }
+    } else if let Some(regex) = regex {
+        if parallel {
+            perform_regex_replacement_parallel(content, regex, replacement, chunk_size)
+        } else {
+            perform_regex_replacement(content, regex, replacement)
+        }
+    } else if let Some(boundary) = &config.word_boundary {
+        let ignore_case = config.case_sensitivity.ignores_case([pattern]);
+        if parallel {
+            perform_word_replacement_parallel(content, pattern, replacement, ignore_case, boundary, chunk_size)
+        } else {
+            perform_word_replacement(content, pattern, replacement, ignore_case, boundary)
+        }
+    } else if config.case_sensitivity.ignores_case([pattern]) {
+        if parallel {
+            perform_replacement_parallel_ignore_case(content, pattern, replacement, chunk_size)
+        } else {
+            perform_replacement_ignore_case(content, pattern, replacement)
+        }
+    } else if parallel {
+        perform_replacement_parallel(content, pattern, replacement, chunk_size)
+    } else {
+        perform_replacement_utf8_optimized(content, pattern, replacement, config.enable_caching)
+    }
+}
+
+/// Find the matches `perform_configured_replacement` replaces, in content order
+///
+/// The same engines are used with the same precedence, in parallel above the parallel
+/// threshold, and give the same matches either way.
+pub fn find_configured_matches(
+    content: &str,
+    pattern: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> Vec<Match> {
+    let chunk_size = if content.len() > config.parallel_threshold {
+        config.parallel_threshold / 2
+    } else {
+        content.len() + 1
+    };
+
+    if let Some(set) = &config.pattern_set {
+        find_pattern_set_matches_parallel(content, set, chunk_size)
+    } else if let Some(regex) = regex {
+        find_regex_matches_parallel(content, regex, chunk_size)
+    } else {
+        let ignore_case = config.case_sensitivity.ignores_case([pattern]);
+        find_matches_parallel(content, pattern, ignore_case, config.word_boundary.as_ref(), chunk_size)
+    }
+}
+
+/// Find the matches `perform_configured_replacement` replaces: those of `find_configured_matches`
+/// on the selected lines, then among them the selected occurrences
+pub fn find_selected_matches(
+    content: &str,
+    pattern: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> Vec<Match> {
+    let mut matches = find_configured_matches(content, pattern, regex, config);
+    if let Some(scope) = &config.line_scope {
+        matches = scope.select(content, matches);
+    }
+    if let Some(occurrences) = &config.occurrences {
+        matches = occurrences.select(matches);
+    }
+    matches
+}
+
+/// Streaming replacement with the engine the configuration calls for, like `perform_configured_replacement`
+///
+/// Reads `config.buffer_size / 4` bytes at a time. With selected occurrences, matches are
+/// numbered as they are found, and once no selected occurrence or line is left the rest of the
+/// input is copied through without searching. Lines tested against a regex are held in memory
+/// until they end. A CRLF split between two reads is matched and converted like any other.
+pub fn perform_streaming_configured_replacement(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> std::io::Result<usize> {
+    let buffer_size = config.buffer_size / 4;
+    let count = match (fixed_line_ending(config), config.eol_agnostic) {
+        (None, false) => return stream_configured(input, output, pattern, replacement, regex, config),
+        (None, true) => {
+            let endings = LineEndings::default();
+            let mut reader = NormalizingReader::new(input, buffer_size, Some(&endings));
+            let mut writer = LineEndingWriter::restoring(output, &endings);
+            let (replacement, config) = mark_replacements(pattern, replacement, regex, config);
+            let count = stream_configured(&mut reader, &mut writer, pattern, &replacement, regex, &config)?;
+            writer.finish()?;
+            count
+        }
+        (Some(crlf), agnostic) => {
+            let mut writer = LineEndingWriter::converting(output, crlf);
+            let count = if agnostic {
+                let mut reader = NormalizingReader::new(input, buffer_size, None);
+                stream_configured(&mut reader, &mut writer, pattern, replacement, regex, config)?
+            } else {
+                stream_configured(input, &mut writer, pattern, replacement, regex, config)?
+            };
+            writer.finish()?;
+            count
+        }
+    };
+    Ok(count)
+}
+
+fn stream_configured(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    config: &ReplacementConfig
+) -> std::io::Result<usize> {
+    let buffer_size = config.buffer_size / 4;
+    let selection = MatchSelection::from_config(config);
+
+    if let Some(set) = &config.pattern_set {
+        return stream_pattern_set_replacement(input, output, set, selection, buffer_size);
+    }
+    if let Some(regex) = regex {
+        return stream_regex_replacement(input, output, regex, replacement, selection, buffer_size);
+    }
+    if pattern.is_empty() {
+        std::io::copy(input, output)?;
+        return Ok(0);
+    }
+
+    let ignore_case = config.case_sensitivity.ignores_case([pattern]);
+    match &config.word_boundary {
+        Some(boundary) => stream_word_replacement(input, output, pattern, replacement, ignore_case, boundary, selection, buffer_size),
+        None if selection.is_all() && ignore_case => perform_streaming_replacement_ignore_case(input, output, pattern, replacement, buffer_size),
+        None if selection.is_all() => perform_streaming_replacement(input, output, pattern, replacement, buffer_size),
+        None => {
+            let needle = if ignore_case { fold_str(pattern) } else { pattern.to_string() };
+            let finder = memmem::Finder::new(needle.as_bytes());
+            perform_streaming_matcher_replacement(
+                input,
+                output,
+                buffer_size,
+                needle.len(),
+                ignore_case,
+                selection,
+                |text, from| finder
+                    .find_iter(&text[from..])
+                    .map(|start| (from + start, from + start + needle.len(), 0))
+                    .collect(),
+                |_, _| Cow::Borrowed(replacement.as_bytes())
+            )
+        }
+    }
+}
+
 /// Multi-pattern replacement using Aho-Corasick algorithm
 pub fn perform_multi_pattern_replacement(content: &str, patterns: &[&str], replacements: &[&str]) -> (String, usize) {
     if patterns.is_empty() || patterns.len() != replacements.len() {
         return (content.to_string(), 0);
     }
-    
+
     let ac = match AhoCorasick::new(patterns) {
         Ok(ac) => ac,
         Err(_) => return (content.to_string(), 0),
     };
-    
+
     let mut result = String::with_capacity(content.len());
     let mut last_end = 0;
     let mut count = 0;
-    
+
     for match_ in ac.find_iter(content) {
         result.push_str(&content[last_end..match_.start()]);
-        result.push_str(&replacements[match_.pattern()]);
+        result.push_str(replacements[match_.pattern()]);
         last_end = match_.end();
         count += 1;
     }
-    
+
     result.push_str(&content[last_end..]);
     (result, count)
 }
 
 /// Streaming replacement for very large files to minimize memory usage
+///
+/// Matching is done on raw bytes: a UTF-8 pattern can only match at character boundaries of
+/// UTF-8 text, so this gives the same result as `perform_replacement` while never needing to
+/// decode, even when a read splits a multi-byte character. After each read, every match that fits
+/// in the buffered data is final; only the last `pattern.len() - 1` bytes, which could start a
+/// match completed by the next read, are carried over.
 pub fn perform_streaming_replacement(
     input: &mut impl std::io::Read,
     output: &mut impl std::io::Write,
@@ -83,59 +438,257 @@ pub fn perform_streaming_replacement(
         std::io::copy(input, output)?;
         return Ok(0);
     }
-    
-    let mut buffer = vec![0u8; buffer_size];
-    let mut overlap = Vec::new();
+
+    let finder = memmem::Finder::new(pattern.as_bytes());
+    let overlap_size = pattern.len() - 1;
+    let mut buffer = vec![0u8; buffer_size.max(1)];
+    let mut pending: Vec<u8> = Vec::with_capacity(buffer.len() + overlap_size);
     let mut total_count = 0;
-    
+
     loop {
         let bytes_read = input.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+        let at_eof = bytes_read == 0;
+        pending.extend_from_slice(&buffer[..bytes_read]);
+
+        let mut last_end = 0;
+        for start in finder.find_iter(&pending) {
+            output.write_all(&pending[last_end..start])?;
+            output.write_all(replacement.as_bytes())?;
+            last_end = start + pattern.len();
+            total_count += 1;
         }
-        
-        let chunk = &buffer[..bytes_read];
-        let mut process_chunk = overlap.clone();
-        process_chunk.extend_from_slice(chunk);
-        
-        // Process the chunk, keeping overlap for pattern boundaries
-        let overlap_size = pattern.len().saturating_sub(1);
-        
-        // Only split if we have enough data
-        if process_chunk.len() <= overlap_size {
-            overlap = process_chunk;
-            continue;
-        }
-        
-        let split_point = process_chunk.len().saturating_sub(overlap_size);
-        let (to_process, new_overlap) = process_chunk.split_at(split_point);
-        overlap = new_overlap.to_vec();
-        
-        // Convert to string for processing (assuming UTF-8 content)
-        if let Ok(chunk_str) = std::str::from_utf8(to_process) {
-            let (replaced, count) = super::sequential::perform_replacement(chunk_str, pattern, replacement);
-            output.write_all(replaced.as_bytes())?;
-            total_count += count;
+
+        // Keep the tail that may be the beginning of a match spanning the next read
+        let keep_from = if at_eof {
+            pending.len()
         } else {
-            // Fallback: write original bytes if not valid UTF-8
-            output.write_all(to_process)?;
+            last_end.max(pending.len().saturating_sub(overlap_size))
+        };
+        output.write_all(&pending[last_end..keep_from])?;
+        pending.drain(..keep_from);
+
+        if at_eof {
+            break;
         }
     }
-    
-    // Process remaining overlap
-    if !overlap.is_empty() {
-        if let Ok(chunk_str) = std::str::from_utf8(&overlap) {
-            let (replaced, count) = super::sequential::perform_replacement(chunk_str, pattern, replacement);
-            output.write_all(replaced.as_bytes())?;
-            total_count += count;
-        } else {
-            output.write_all(&overlap)?;
+
+    Ok(total_count)
+}
+
+/// Case-insensitive variant of `perform_streaming_replacement`, with Unicode simple case folding
+pub fn perform_streaming_replacement_ignore_case(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+    pattern: &str,
+    replacement: &str,
+    buffer_size: usize
+) -> std::io::Result<usize> {
+    if pattern.is_empty() {
+        std::io::copy(input, output)?;
+        return Ok(0);
+    }
+
+    let folded_pattern = fold_str(pattern);
+    let finder = memmem::Finder::new(folded_pattern.as_bytes());
+    perform_streaming_matcher_replacement(
+        input,
+        output,
+        buffer_size,
+        folded_pattern.len(),
+        true,
+        MatchSelection::default(),
+        |text, from| finder
+            .find_iter(&text[from..])
+            .map(|start| (from + start, from + start + folded_pattern.len(), 0))
+            .collect(),
+        |_, _| Cow::Borrowed(replacement.as_bytes())
+    )
+}
+
+/// Whole-word variant of `perform_streaming_replacement`, case-insensitive if asked
+pub fn perform_streaming_word_replacement(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+    pattern: &str,
+    replacement: &str,
+    ignore_case: bool,
+    boundary: &WordBoundary,
+    buffer_size: usize
+) -> std::io::Result<usize> {
+    if pattern.is_empty() {
+        std::io::copy(input, output)?;
+        return Ok(0);
+    }
+
+    stream_word_replacement(input, output, pattern, replacement, ignore_case, boundary, MatchSelection::default(), buffer_size)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn stream_word_replacement(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+    pattern: &str,
+    replacement: &str,
+    ignore_case: bool,
+    boundary: &WordBoundary,
+    selection: MatchSelection,
+    buffer_size: usize
+) -> std::io::Result<usize> {
+    let needle = if ignore_case { fold_str(pattern) } else { pattern.to_string() };
+    perform_streaming_matcher_replacement(
+        input,
+        output,
+        buffer_size,
+        // Room for the character following a match
+        needle.len() + 4,
+        ignore_case,
+        selection,
+        |text, from| find_filtered_match_positions(text, needle.as_bytes(), &[(from, text.len())], |start| {
+            boundary.is_bounded(text, start, start + needle.len())
+        })
+            .into_iter()
+            .map(|start| (start, start + needle.len(), 0))
+            .collect(),
+        |_, _| Cow::Borrowed(replacement.as_bytes())
+    )
+}
+
+/// Which of the matches found get replaced: those on selected lines, then among them the
+/// selected occurrences
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct MatchSelection<'a> {
+    pub lines: Option<&'a LineScope>,
+    pub occurrences: Option<&'a Occurrences>,
+}
+
+impl<'a> MatchSelection<'a> {
+    pub(crate) fn from_config(config: &'a ReplacementConfig) -> Self {
+        MatchSelection { lines: config.line_scope.as_ref(), occurrences: config.occurrences.as_ref() }
+    }
+
+    /// Whether every match gets replaced
+    pub(crate) fn is_all(&self) -> bool {
+        self.lines.is_none() && self.occurrences.is_none()
+    }
+
+    /// Whether nothing from here on can be selected, given the line tracker positioned at `pos`
+    pub(crate) fn is_done(&self, lines: Option<&mut LineTracker>, text: &[u8], pos: usize) -> bool {
+        self.occurrences.is_some_and(Occurrences::is_exhausted)
+            || lines.is_some_and(|lines| lines.is_past(text, pos))
+    }
+}
+
+/// Streaming replacement driven by any matcher, for the case-insensitive, whole-word and pattern set ones
+///
+/// Each read is searched with `find(text, from)`, which returns the `(start, end, replacement
+/// index)` spans starting at or after `from`; the bytes before `from` are the last character
+/// already written, kept so that word boundaries can be checked. With `fold`, the text searched is
+/// the case-folded copy of the data and spans are in folded offsets. `replacement` is given the
+/// index and the matched original bytes. As in the exact streaming replacements, a match is final
+/// once `max_len` bytes fit between its start and the end of the data, and only the tail where a
+/// match may still start is carried over, along with any character cut by the read. Final
+/// matches are filtered through `selection`; when its lines are tested against a regex, a match
+/// is only final once its last line has ended, and the lines it is on are carried over whole.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn perform_streaming_matcher_replacement<'r>(
+    input: &mut impl std::io::Read,
+    output: &mut impl std::io::Write,
+    buffer_size: usize,
+    max_len: usize,
+    fold: bool,
+    selection: MatchSelection,
+    mut find: impl FnMut(&[u8], usize) -> Vec<(usize, usize, usize)>,
+    replacement: impl Fn(usize, &[u8]) -> Cow<'r, [u8]>
+) -> std::io::Result<usize> {
+    let mut buffer = vec![0u8; buffer_size.max(1)];
+    let mut pending: Vec<u8> = Vec::new();
+    // Bytes at the start of `pending` that were already written
+    let mut written = 0;
+    let mut total_count = 0;
+    let mut lines = selection.lines.map(LineTracker::new);
+
+    loop {
+        let bytes_read = input.read(&mut buffer)?;
+        let at_eof = bytes_read == 0;
+        pending.extend_from_slice(&buffer[..bytes_read]);
+
+        let complete = if at_eof { pending.len() } else { complete_utf8_len(&pending) };
+        let folded = fold.then(|| FoldedText::new(&pending[..complete]));
+        let text = folded.as_ref().map_or(&pending[..complete], FoldedText::as_bytes);
+        let original = |pos| folded.as_ref().map_or(pos, |folded| folded.original_offset(pos));
+        let from = match &folded {
+            Some(_) => FoldedText::new(&pending[..written]).as_bytes().len(),
+            None => written,
+        };
+
+        let mut last_end = written;
+        let mut last_text_end = from;
+        // Start of a match left for the next read because its lines have not ended yet
+        let mut deferred = None;
+        for (start, end, index) in find(text, from) {
+            if !at_eof && start + max_len > text.len() {
+                break;
+            }
+            let (original_start, original_end) = (original(start), original(end));
+            if let Some(lines) = &mut lines {
+                match lines.selects(&pending[..complete], original_start, original_end, at_eof) {
+                    Some(true) => {}
+                    Some(false) => {
+                        last_text_end = end;
+                        continue;
+                    }
+                    None => {
+                        deferred = Some(start);
+                        break;
+                    }
+                }
+            }
+            last_text_end = end;
+            if let Some(occurrences) = selection.occurrences
+                && !occurrences.contains(occurrences.next_numbers(1)) {
+                continue;
+            }
+            output.write_all(&pending[last_end..original_start])?;
+            output.write_all(&replacement(index, &pending[original_start..original_end]))?;
+            last_end = original_end;
+            total_count += 1;
+        }
+
+        if at_eof || selection.is_done(lines.as_mut(), &pending, original(last_text_end)) {
+            output.write_all(&pending[last_end..])?;
+            std::io::copy(input, output)?;
+            break;
         }
+
+        let tail = deferred.unwrap_or_else(|| last_text_end.max(text.len().saturating_sub(max_len.saturating_sub(1))));
+        let keep_from = match &folded {
+            Some(folded) => folded.original_floor(tail),
+            None => floor_char_start(&pending, tail),
+        };
+        output.write_all(&pending[last_end..keep_from])?;
+        let mut context_start = if keep_from == 0 { 0 } else { floor_char_start(&pending, keep_from - 1) };
+        if let Some(lines) = &mut lines {
+            context_start = context_start.min(lines.keep_from(&pending, keep_from));
+            lines.drain(&pending, context_start);
+        }
+        pending.drain(..context_start);
+        written = keep_from - context_start;
     }
-    
+
+    output.flush()?;
     Ok(total_count)
 }
 
+/// Start of the UTF-8 character containing `pos`, stepping back over at most three continuation bytes
+fn floor_char_start(bytes: &[u8], mut pos: usize) -> usize {
+    let mut steps = 0;
+    while pos > 0 && pos < bytes.len() && bytes[pos] & 0xC0 == 0x80 && steps < 3 {
+        pos -= 1;
+        steps += 1;
+    }
+    pos
+}
+
 fn should_use_ascii_optimization(content: &str, pattern: &str, replacement: &str) -> bool {
     content.is_ascii() && pattern.is_ascii() && replacement.is_ascii()
 }
@@ -143,12 +696,12 @@ fn should_use_ascii_optimization(content: &str, pattern: &str, replacement: &str
 fn generate_cache_key(content: &str, pattern: &str, replacement: &str) -> String {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
-    
+
     let mut hasher = DefaultHasher::new();
     content.as_bytes().hash(&mut hasher);
     pattern.as_bytes().hash(&mut hasher);
     replacement.as_bytes().hash(&mut hasher);
-    
+
     format!("{}:{}:{}", content.len(), pattern.len(), hasher.finish())
 }
 
@@ -161,10 +714,10 @@ mod tests {
         let content = "test test test";
         let pattern = "test";
         let replacement = "TEST";
-        
+
         let (result_optimized, count_optimized) = perform_replacement_utf8_optimized(content, pattern, replacement, false);
         let (result_sequential, count_sequential) = perform_replacement(content, pattern, replacement);
-        
+
         assert_eq!(result_optimized, result_sequential);
         assert_eq!(count_optimized, count_sequential);
         assert_eq!(count_optimized, 3);
@@ -175,23 +728,35 @@ mod tests {
         let content = "héllo héllo héllo";
         let pattern = "héllo";
         let replacement = "hello";
-        
+
         let (result_optimized, count_optimized) = perform_replacement_utf8_optimized(content, pattern, replacement, false);
         let (result_sequential, count_sequential) = perform_replacement(content, pattern, replacement);
-        
+
         assert_eq!(result_optimized, result_sequential);
         assert_eq!(count_optimized, count_sequential);
         assert_eq!(count_optimized, 3);
     }
 
+    #[test]
+    fn test_find_matches() {
+        let content = "Ünïcode ünïcode \u{212A}elvin";
+        let matches = find_matches(content, "ünïcode", true);
+        assert_eq!(matches.iter().map(|m| m.as_str(content)).collect::<Vec<_>>(), ["Ünïcode", "ünïcode"]);
+        assert_eq!(find_matches(content, "kelvin", true)[0].as_str(content), "\u{212A}elvin");
+        assert_eq!(find_matches(content, "ünïcode", false), [Match { start: 10, end: 19, pattern: 0 }]);
+
+        let (result, count) = replace_matches(content, &matches, |m| Cow::Owned(m.as_str(content).to_uppercase()));
+        assert_eq!((result.as_str(), count), ("ÜNÏCODE ÜNÏCODE \u{212A}elvin", 2));
+    }
+
     #[test]
     fn test_multi_pattern_replacement() {
         let content = "apple banana cherry apple";
         let patterns = &["apple", "banana"];
         let replacements = &["fruit", "yellow"];
-        
+
         let (result, count) = perform_multi_pattern_replacement(content, patterns, replacements);
-        
+
         assert_eq!(result, "fruit yellow cherry fruit");
         assert_eq!(count, 3);
     }
@@ -201,15 +766,264 @@ mod tests {
         let content = "test test test";
         let pattern = "test";
         let replacement = "TEST";
-        
+
         // First call (no cache)
         let (result1, count1) = perform_replacement_utf8_optimized(content, pattern, replacement, true);
-        
+
         // Second call (should use cache)
         let (result2, count2) = perform_replacement_utf8_optimized(content, pattern, replacement, true);
-        
+
         assert_eq!(result1, result2);
         assert_eq!(count1, count2);
         assert_eq!(count1, 3);
     }
+
+    /// Reader producing `repeat` copies of `unit` without holding them in memory
+    struct RepeatReader {
+        unit: &'static [u8],
+        remaining: u64,
+        offset: usize,
+    }
+
+    impl std::io::Read for RepeatReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut written = 0;
+            while written < buf.len() && self.remaining > 0 {
+                let available = &self.unit[self.offset..];
+                let n = available.len().min(buf.len() - written);
+                buf[written..written + n].copy_from_slice(&available[..n]);
+                written += n;
+                self.offset += n;
+                if self.offset == self.unit.len() {
+                    self.offset = 0;
+                    self.remaining -= 1;
+                }
+            }
+            Ok(written)
+        }
+    }
+
+    /// Writer checking that its input is `repeat` copies of `unit`
+    struct RepeatChecker {
+        unit: &'static [u8],
+        offset: usize,
+        total: u64,
+    }
+
+    impl std::io::Write for RepeatChecker {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            for &byte in buf {
+                assert_eq!(byte, self.unit[self.offset], "output differs at byte {}", self.total);
+                self.offset = (self.offset + 1) % self.unit.len();
+                self.total += 1;
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streaming_matches_sequential() {
+        let content = "héllo wörld, hello world! ".repeat(200);
+        for pattern in ["héllo", "wörld, h", "o", "!", " héllo wörld, hello world! "] {
+            let expected = perform_replacement(&content, pattern, "ünïcode");
+            for buffer_size in [1, 2, 3, 7, 64, 4096] {
+                let mut output = Vec::new();
+                let count = perform_streaming_replacement(&mut content.as_bytes(), &mut output, pattern, "ünïcode", buffer_size).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "pattern {pattern:?}, buffer size {buffer_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_ignore_case_matches_sequential() {
+        let content = "Héllo HÉLLO \u{212A}elvin kelvin ſun SUN, héllo! ".repeat(100);
+        for pattern in ["héllo", "kelvin", "sun", "o h", "N, H"] {
+            let expected = perform_replacement_ignore_case(&content, pattern, "ü");
+            for buffer_size in [1, 2, 3, 7, 64, 4096] {
+                let mut output = Vec::new();
+                let count = perform_streaming_replacement_ignore_case(&mut content.as_bytes(), &mut output, pattern, "ü", buffer_size).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "pattern {pattern:?}, buffer size {buffer_size}");
+            }
+        }
+
+        let mut output = Vec::new();
+        perform_streaming_replacement_ignore_case(&mut &b"\xFFTest\xC3TEST\xE9"[..], &mut output, "test", "x", 2).unwrap();
+        assert_eq!(output, b"\xFFx\xC3x\xE9");
+    }
+
+    #[test]
+    fn test_streaming_word_matches_sequential() {
+        let content = "id width Id valid (id) idé id_x ID-id ".repeat(50);
+        let boundary = WordBoundary::unicode();
+        for (pattern, ignore_case) in [("id", false), ("id", true), ("d w", false), ("id-id", true)] {
+            let expected = perform_word_replacement(&content, pattern, "ü", ignore_case, &boundary);
+            for buffer_size in [1, 2, 3, 7, 64, 4096] {
+                let mut output = Vec::new();
+                let count = perform_streaming_word_replacement(&mut content.as_bytes(), &mut output, pattern, "ü", ignore_case, &boundary, buffer_size).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "pattern {pattern:?}, buffer size {buffer_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_occurrences_across_engines() {
+        use crate::core::{CaseSensitivity, MatchKind, build_pattern_set, build_regex};
+        use std::sync::Arc;
+
+        let content = "id idé Id width (id) ".repeat(40);
+        let set = build_pattern_set(&[("id".to_string(), "A".to_string()), ("é".to_string(), "E".to_string())], MatchKind::default(), false).unwrap();
+        let regex = build_regex(r"(i)d").unwrap();
+        let configs = [
+            (None, ReplacementConfig::new()),
+            (None, ReplacementConfig::new().with_case_sensitivity(CaseSensitivity::Insensitive)),
+            (None, ReplacementConfig::new().with_word_boundary(Some(WordBoundary::unicode()))),
+            (None, ReplacementConfig::new().with_pattern_set(Some(Arc::new(set)))),
+            (Some(&regex), ReplacementConfig::new()),
+        ];
+
+        for (regex, config) in configs {
+            let expected = {
+                let config = config.clone().with_occurrences(Some(Occurrences::parse("2..4,50").unwrap()));
+                perform_configured_replacement(&content, "id", "${1}X", regex, &config)
+            };
+            assert_eq!(expected.1, 4);
+
+            for threshold in [1, 7, 64] {
+                let config = config.clone()
+                    .with_parallel_threshold(threshold)
+                    .with_occurrences(Some(Occurrences::parse("2..4,50").unwrap()));
+                assert_eq!(perform_configured_replacement(&content, "id", "${1}X", regex, &config), expected);
+            }
+            for buffer_size in [4, 12, 4096] {
+                let config = config.clone()
+                    .with_buffer_size(buffer_size)
+                    .with_occurrences(Some(Occurrences::parse("2..4,50").unwrap()));
+                let mut output = Vec::new();
+                let count = perform_streaming_configured_replacement(&mut content.as_bytes(), &mut output, "id", "${1}X", regex, &config).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_scope_across_engines() {
+        use crate::core::{CaseSensitivity, LineScope, MatchKind, build_pattern_set, build_regex};
+        use std::sync::Arc;
+
+        let content: String = (0..40).map(|i| format!("{i} id idé Id width (id)\n")).collect();
+        let set = build_pattern_set(&[("id".to_string(), "A".to_string()), ("\n1".to_string(), "\nB".to_string())], MatchKind::default(), false).unwrap();
+        let regex = build_regex(r"(i)d|\n3").unwrap();
+        let configs = [
+            (None, ReplacementConfig::new()),
+            (None, ReplacementConfig::new().with_case_sensitivity(CaseSensitivity::Insensitive)),
+            (None, ReplacementConfig::new().with_word_boundary(Some(WordBoundary::unicode()))),
+            (None, ReplacementConfig::new().with_pattern_set(Some(Arc::new(set)))),
+            (Some(&regex), ReplacementConfig::new()),
+        ];
+        let scope = LineScope::parse("3:30").unwrap().with_not_matching(Some(build_regex("^1").unwrap()));
+
+        for (index, (regex, config)) in configs.into_iter().enumerate() {
+            let config = config.with_line_scope(Some(scope.clone()));
+            let expected = perform_configured_replacement(&content, "id", "${1}X", regex, &config);
+            if index == 0 {
+                // Four matches on each of lines 3 to 30, except the ten starting with 1
+                assert_eq!(expected.1, 72);
+            }
+
+            for threshold in [1, 7, 64] {
+                let config = config.clone().with_parallel_threshold(threshold);
+                assert_eq!(perform_configured_replacement(&content, "id", "${1}X", regex, &config), expected);
+            }
+            for buffer_size in [4, 12, 4096] {
+                for occurrences in [None, Some("5..9,40")] {
+                    let config = config.clone()
+                        .with_buffer_size(buffer_size)
+                        .with_occurrences(occurrences.map(|list| Occurrences::parse(list).unwrap()));
+                    let expected = perform_configured_replacement(&content, "id", "${1}X", regex, &config.for_file());
+                    let mut output = Vec::new();
+                    let count = perform_streaming_configured_replacement(&mut content.as_bytes(), &mut output, "id", "${1}X", regex, &config).unwrap();
+                    assert_eq!((String::from_utf8(output).unwrap(), count), expected, "engine {index}, buffer size {buffer_size}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_eol_agnostic_across_engines() {
+        use crate::core::{CaseSensitivity, MatchKind, build_pattern_set, build_regex};
+        use std::sync::Arc;
+
+        let content = "(id) a\r\nid\nb id\r\n".repeat(30);
+        let set = build_pattern_set(&[("id".to_string(), "A\n".to_string()), ("\nb".to_string(), "\nB\n".to_string())], MatchKind::default(), false).unwrap();
+        let regex = build_regex(r"(i)d|\nb").unwrap();
+        let configs = [
+            (None, ReplacementConfig::new()),
+            (None, ReplacementConfig::new().with_case_sensitivity(CaseSensitivity::Insensitive)),
+            (None, ReplacementConfig::new().with_word_boundary(Some(WordBoundary::unicode()))),
+            (None, ReplacementConfig::new().with_pattern_set(Some(Arc::new(set)))),
+            (Some(&regex), ReplacementConfig::new()),
+        ];
+
+        for (index, (regex, config)) in configs.into_iter().enumerate() {
+            let config = config.with_eol_agnostic(true);
+            // Without a match, the content comes back as it was
+            let unmatched = if regex.is_some() { Some(build_regex("none").unwrap()) } else { None };
+            let unmatched_config = ReplacementConfig { pattern_set: None, ..config.clone() };
+            assert_eq!(perform_configured_replacement(&content, "none", "${1}\nX", unmatched.as_ref(), &unmatched_config), (content.clone(), 0));
+            let mut output = Vec::new();
+            let unmatched_config = unmatched_config.with_buffer_size(4);
+            perform_streaming_configured_replacement(&mut content.as_bytes(), &mut output, "none", "${1}\nX", unmatched.as_ref(), &unmatched_config).unwrap();
+            assert_eq!(output, content.as_bytes());
+
+            let expected = perform_configured_replacement(&content, "id", "${1}\nX", regex, &config);
+            if index == 0 {
+                // A replacement's line ending takes the file's first one, as the matched text has none
+                assert_eq!(expected, (content.replace("id", "${1}\r\nX"), 90));
+            }
+
+            for threshold in [1, 7, 64] {
+                let config = config.clone().with_parallel_threshold(threshold);
+                assert_eq!(perform_configured_replacement(&content, "id", "${1}\nX", regex, &config), expected, "engine {index}");
+            }
+            for (buffer_size, spec) in [4, 12, 4096].into_iter().flat_map(|size| [(size, None), (size, Some("2..4,50"))]) {
+                let occurrences = || spec.map(|spec| Occurrences::parse(spec).unwrap());
+                let expected = match spec {
+                    Some(_) => perform_configured_replacement(&content, "id", "${1}\nX", regex, &config.clone().with_occurrences(occurrences())),
+                    None => expected.clone(),
+                };
+                let config = config.clone().with_buffer_size(buffer_size).with_occurrences(occurrences());
+                let mut output = Vec::new();
+                let count = perform_streaming_configured_replacement(&mut content.as_bytes(), &mut output, "id", "${1}\nX", regex, &config).unwrap();
+                assert_eq!((String::from_utf8(output).unwrap(), count), expected, "engine {index}, buffer size {buffer_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_keeps_invalid_utf8() {
+        let content = b"abc\xFFtest\xC3test\xE9";
+        let mut output = Vec::new();
+        let count = perform_streaming_replacement(&mut &content[..], &mut output, "test", "TEST", 3).unwrap();
+        assert_eq!(output, b"abc\xFFTEST\xC3TEST\xE9");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    #[ignore = "streams several gigabytes; run with `cargo test --release -- --ignored`"]
+    fn test_streaming_multi_gigabyte() {
+        // 4.5 GiB, so offsets do not fit in 32 bits, with matches straddling every read boundary
+        let unit: &'static [u8] = b"abcdefghijklmnopqrstuvwxyz_needle_0123456789\n";
+        let repeat = (9u64 << 29) / unit.len() as u64;
+        let mut reader = RepeatReader { unit, remaining: repeat, offset: 0 };
+        let mut writer = RepeatChecker { unit: b"abcdefghijklmnopqrstuvwxyz_NEEDLE!_0123456789\n", offset: 0, total: 0 };
+
+        let count = perform_streaming_replacement(&mut reader, &mut writer, "needle", "NEEDLE!", 1 << 20).unwrap();
+
+        assert_eq!(count as u64, repeat);
+        assert_eq!(writer.total, repeat * (unit.len() as u64 + 1));
+    }
 }
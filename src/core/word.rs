@@ -0,0 +1,149 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::io;
+use std::sync::Arc;
+use regex_syntax::hir::{Class, ClassUnicode, ClassUnicodeRange, HirKind};
+use crate::core::case::{FoldedText, fold_str};
+use crate::core::parallel::find_filtered_match_positions;
+use crate::core::specialized::{Match, replace_matches};
+
+/// Which characters make up words, for whole-word matching
+///
+/// A match is only kept when it is bounded by non-word characters, or the ends of the content, on
+/// both sides. By default word characters are Unicode `\w` (letters, marks, digits and connector
+/// punctuation); a custom class such as `[\w-]` also counts `-`, as in CSS or Lisp identifiers.
+#[derive(Debug, Clone, Default)]
+pub struct WordBoundary {
+    /// Custom word characters, closed under simple case folding so that checking folded text gives
+    /// the same answer; `None` for `\w`
+    class: Option<Arc<ClassUnicode>>,
+}
+
+impl WordBoundary {
+    /// Word characters are Unicode `\w`
+    pub fn unicode() -> Self {
+        Self::default()
+    }
+
+    /// Word characters are those of a regex character class, e.g. `[\w-]` or `[[:alnum:]_$]`
+    pub fn from_class(class: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let hir = regex_syntax::parse(class).map_err(|e| invalid(e.to_string()))?;
+
+        let mut class = match hir.kind() {
+            HirKind::Class(Class::Unicode(class)) => class.clone(),
+            // A class of a single character, like `[-]`, parses as that character
+            HirKind::Literal(literal) => {
+                let mut chars = std::str::from_utf8(&literal.0).unwrap_or_default().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => ClassUnicode::new([ClassUnicodeRange::new(c, c)]),
+                    _ => return Err(invalid(format!("'{}' is not a character class", class))),
+                }
+            }
+            _ => return Err(invalid(format!("'{}' is not a character class", class))),
+        };
+        class.case_fold_simple();
+        Ok(WordBoundary { class: Some(Arc::new(class)) })
+    }
+
+    pub fn is_word_char(&self, c: char) -> bool {
+        match &self.class {
+            Some(class) => class.ranges()
+                .binary_search_by(|range| {
+                    if range.end() < c {
+                        Ordering::Less
+                    } else if range.start() > c {
+                        Ordering::Greater
+                    } else {
+                        Ordering::Equal
+                    }
+                })
+                .is_ok(),
+            None => regex_syntax::is_word_character(c),
+        }
+    }
+
+    /// Whether `text[start..end]` has no word character right before or right after it
+    ///
+    /// Invalid UTF-8 around the match counts as a non-word character.
+    pub fn is_bounded(&self, text: &[u8], start: usize, end: usize) -> bool {
+        let before = text[start.saturating_sub(4)..start]
+            .utf8_chunks()
+            .last()
+            .filter(|chunk| chunk.invalid().is_empty())
+            .and_then(|chunk| chunk.valid().chars().next_back());
+        let after = text[end..(end + 4).min(text.len())]
+            .utf8_chunks()
+            .next()
+            .and_then(|chunk| chunk.valid().chars().next());
+
+        !before.is_some_and(|c| self.is_word_char(c)) && !after.is_some_and(|c| self.is_word_char(c))
+    }
+}
+
+/// Find the non-overlapping whole-word matches of a literal pattern, from left to right
+///
+/// An occurrence that is not bounded is skipped without hiding the occurrences overlapping it, so
+/// `aa` is found at the end of `aaa aa`.
+pub fn find_word_matches(content: &str, pattern: &str, ignore_case: bool, boundary: &WordBoundary) -> Vec<Match> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let folded = ignore_case.then(|| FoldedText::new(content.as_bytes()));
+    let needle = if ignore_case { fold_str(pattern) } else { pattern.to_string() };
+    let text = folded.as_ref().map_or(content.as_bytes(), FoldedText::as_bytes);
+    let original = |pos| folded.as_ref().map_or(pos, |folded| folded.original_offset(pos));
+
+    find_filtered_match_positions(text, needle.as_bytes(), &[(0, text.len())], |start| {
+        boundary.is_bounded(text, start, start + needle.len())
+    })
+        .into_iter()
+        .map(|start| Match { start: original(start), end: original(start + needle.len()), pattern: 0 })
+        .collect()
+}
+
+/// Replace the whole-word occurrences of a literal pattern
+pub fn perform_word_replacement(
+    content: &str,
+    pattern: &str,
+    replacement: &str,
+    ignore_case: bool,
+    boundary: &WordBoundary
+) -> (String, usize) {
+    let matches = find_word_matches(content, pattern, ignore_case, boundary);
+    replace_matches(content, &matches, |_| Cow::Borrowed(replacement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whole_words() {
+        let boundary = WordBoundary::unicode();
+        let content = "id width valid id_x (id) idé id";
+        assert_eq!(
+            perform_word_replacement(content, "id", "key", false, &boundary),
+            ("key width valid id_x (key) idé key".to_string(), 3)
+        );
+        assert_eq!(perform_word_replacement("aaa aa", "aa", "b", false, &boundary), ("aaa b".to_string(), 1));
+        assert_eq!(perform_word_replacement("ID Id iD", "id", "x", true, &boundary), ("x x x".to_string(), 3));
+    }
+
+    #[test]
+    fn test_custom_boundary() {
+        let boundary = WordBoundary::from_class(r"[\w-]").unwrap();
+        let content = "margin margin-top -margin margin";
+        assert_eq!(
+            perform_word_replacement(content, "margin", "padding", false, &boundary),
+            ("padding margin-top -margin padding".to_string(), 2)
+        );
+
+        let boundary = WordBoundary::from_class("[a-z]").unwrap();
+        assert!(boundary.is_word_char('Q'));
+        assert!(!boundary.is_word_char('_'));
+        assert!(WordBoundary::from_class("[-]").unwrap().is_word_char('-'));
+        assert!(WordBoundary::from_class("ab").is_err());
+    }
+}
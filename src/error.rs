@@ -0,0 +1,180 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Errors of the file-level API
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing failed, on the given file when known
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// An invalid pattern, regex, character class, range or mapping file
+    InvalidInput(String),
+    /// A warning turned into an error by strict mode; nothing was written
+    Warning { path: Option<PathBuf>, warning: Warning },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Attach the file the error happened on, unless one is already known
+    pub fn with_path(self, path: &Path) -> Self {
+        match self {
+            Error::Io { path: None, source } => Error::Io { path: Some(path.to_path_buf()), source },
+            Error::Warning { path: None, warning } => Error::Warning { path: Some(path.to_path_buf()), warning },
+            error => error,
+        }
+    }
+
+    /// An argument the lower layers rejected, which they report as an `InvalidInput` I/O error
+    pub(crate) fn invalid_input(error: io::Error) -> Self {
+        Error::InvalidInput(error.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => source.fmt(f),
+            Error::InvalidInput(message) => f.write_str(message),
+            Error::Warning { path: Some(path), warning } => write!(f, "{}: {}", path.display(), warning),
+            Error::Warning { path: None, warning } => warning.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// In strict mode, the first of `warnings` as an error
+pub(crate) fn check_strict(strict: bool, path: Option<&Path>, warnings: &[Warning]) -> Result<()> {
+    match warnings.first() {
+        Some(warning) if strict => Err(Error::Warning { path: path.map(Path::to_path_buf), warning: warning.clone() }),
+        _ => Ok(()),
+    }
+}
+
+/// An I/O error, whatever its kind, unless it carries an error of this crate
+///
+/// Invalid arguments are turned into `Error::InvalidInput` where they are validated, with
+/// `Error::invalid_input`.
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        // An error of this crate passed through an `io::Result` callback comes back as it was
+        if error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = error.into_inner().expect("checked above");
+            return *inner.downcast::<Error>().expect("checked above");
+        }
+        Error::Io { path: None, source: error }
+    }
+}
+
+/// For callers that keep to `io::Result`, e.g. inside a writer callback
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io { path: None, source } => source,
+            error => {
+                let kind = match &error {
+                    Error::Io { source, .. } => source.kind(),
+                    Error::InvalidInput(_) => io::ErrorKind::InvalidInput,
+                    _ => io::ErrorKind::Other,
+                };
+                io::Error::new(kind, error)
+            }
+        }
+    }
+}
+
+/// A problem that did not stop the processing of a file, but may have altered its content
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The encoding label is unknown, so UTF-8 was used
+    UnknownEncoding { label: String },
+    /// The content is neither valid UTF-8 nor marked by a BOM, so its encoding was guessed
+    GuessedEncoding { encoding: &'static str },
+    /// Some bytes are invalid in the encoding and were decoded as U+FFFD
+    DecodeErrors { encoding: &'static str },
+    /// Some characters cannot be represented in the encoding and were written as fallbacks
+    UnencodableCharacters { encoding: &'static str },
+}
+
+impl Warning {
+    /// A stable identifier for the kind of warning, e.g. `decode-errors`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnknownEncoding { .. } => "unknown-encoding",
+            Warning::GuessedEncoding { .. } => "guessed-encoding",
+            Warning::DecodeErrors { .. } => "decode-errors",
+            Warning::UnencodableCharacters { .. } => "unencodable-characters",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnknownEncoding { label } => write!(f, "Unknown encoding label '{}'. Defaulting to UTF-8.", label),
+            Warning::GuessedEncoding { encoding } => write!(f, "Input file is not valid UTF-8 and has no BOM. Decoding it as '{}', a guess.", encoding),
+            Warning::DecodeErrors { .. } => f.write_str(
+                "Some characters could not be decoded from the detected/specified encoding. They might be replaced with U+FFFD (replacement character)."
+            ),
+            Warning::UnencodableCharacters { encoding } => write!(
+                f,
+                "Some characters in the replacement string could not be represented in the target encoding ('{}'). They may have been replaced by fallback characters.",
+                encoding
+            ),
+        }
+    }
+}
+
+/// Serialized as `{"code": ..., "message": ...}`
+impl Serialize for Warning {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut warning = serializer.serialize_struct("Warning", 2)?;
+        warning.serialize_field("code", self.code())?;
+        warning.serialize_field("message", &self.to_string())?;
+        warning.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_from_io() {
+        // Only the validation of an argument makes an invalid input, not the kind of an OS error
+        let error = Error::from(io::Error::from_raw_os_error(22)).with_path(Path::new("a.txt"));
+        assert!(matches!(&error, Error::Io { path: Some(_), source } if source.kind() == io::ErrorKind::InvalidInput));
+        let error = Error::invalid_input(io::Error::new(io::ErrorKind::InvalidInput, "bad range"));
+        assert!(matches!(error, Error::InvalidInput(ref message) if message == "bad range"));
+        let error = Error::from(io::Error::from(error));
+        assert!(matches!(error, Error::InvalidInput(ref message) if message == "bad range"));
+
+        let error = Error::from(io::Error::from(io::ErrorKind::NotFound)).with_path(Path::new("a.txt"));
+        assert!(matches!(error, Error::Io { path: Some(_), .. }));
+        assert!(error.to_string().starts_with("a.txt: "));
+
+        let warning = Error::Warning { path: None, warning: Warning::GuessedEncoding { encoding: "KOI8-R" } };
+        let error = Error::from(io::Error::from(warning));
+        assert!(matches!(error, Error::Warning { warning: Warning::GuessedEncoding { .. }, .. }));
+    }
+
+    #[test]
+    fn test_warning_serialization() {
+        let warning = Warning::DecodeErrors { encoding: "UTF-8" };
+        let value = serde_json::to_value(&warning).unwrap();
+        assert_eq!(value["code"], "decode-errors");
+        assert_eq!(value["message"], warning.to_string());
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs::{self, File, FileTimes, Metadata};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::core::ReplacementConfig;
+use super::backup::create_backup;
+use super::journal::{HashingWriter, Journal};
+
+/// Write a file without ever leaving it truncated or half-written
+///
+/// When the target already exists, the content goes to a temporary file in the same directory,
+/// which is synced and then renamed over the target, so a crash or a full disk leaves the original
+/// intact. Symlinks are followed, and the original's permissions and (when allowed) ownership are
+/// carried over; timestamps too when `config.preserve_timestamps` is set. The original is backed up
+/// right before the rename when a backup mode is configured, and recorded in `journal` if one is
+/// given. A target that does not exist yet is simply created.
+pub fn write_file_atomic(
+    path: &Path,
+    config: &ReplacementConfig,
+    journal: Option<&Journal>,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>
+) -> io::Result<()> {
+    write_file_atomic_if_changed(path, config, journal, |writer| write(writer).map(|()| true)).map(|_| ())
+}
+
+/// `write_file_atomic`, where `write` returns whether the content it wrote differs from the target's
+///
+/// When it does not, the temporary file is discarded and the target is left alone: no backup, no
+/// journal entry, not even a new modification time. A target that does not exist yet is always
+/// written. Returns whether the target was written.
+pub(crate) fn write_file_atomic_if_changed(
+    path: &Path,
+    config: &ReplacementConfig,
+    journal: Option<&Journal>,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<bool>
+) -> io::Result<bool> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let staged = journal.map(|journal| journal.stage(path)).transpose()?;
+            let mut writer = BufWriter::with_capacity(config.buffer_size, File::create(path)?);
+            let (new_sha256, _) = write_content(&mut writer, staged.is_some(), write)?;
+            writer.flush()?;
+            if let (Some(journal), Some(staged), Some(new_sha256)) = (journal, staged, new_sha256) {
+                journal.commit(path, staged, new_sha256)?;
+            }
+            return Ok(true);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let metadata = fs::metadata(&target)?;
+    let dir = target.parent().unwrap_or(Path::new("."));
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(".replacer-")
+        .tempfile_in(dir)?;
+
+    let (new_sha256, changed) = {
+        let mut writer = BufWriter::with_capacity(config.buffer_size, temp_file.as_file_mut());
+        let written = write_content(&mut writer, journal.is_some(), write)?;
+        writer.flush()?;
+        written
+    };
+    if !changed {
+        return Ok(false);
+    }
+
+    copy_metadata(temp_file.as_file(), &metadata, config.preserve_timestamps)?;
+    temp_file.as_file().sync_all()?;
+    create_backup(&target, config)?;
+    let staged = journal.map(|journal| journal.stage(&target)).transpose()?;
+    temp_file.persist(&target).map_err(|e| e.error)?;
+
+    if let (Some(journal), Some(staged), Some(new_sha256)) = (journal, staged, new_sha256) {
+        journal.commit(&target, staged, new_sha256)?;
+    }
+
+    // Make the rename itself durable; not every platform can sync a directory, so this is best effort
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(true)
+}
+
+/// Run the caller's write, hashing the content on the way when it has to be journaled
+fn write_content(
+    writer: &mut dyn Write,
+    hash: bool,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<bool>
+) -> io::Result<(Option<String>, bool)> {
+    if !hash {
+        return Ok((None, write(writer)?));
+    }
+
+    let mut hashing = HashingWriter::new(writer);
+    let changed = write(&mut hashing)?;
+    Ok((Some(hashing.finish()), changed))
+}
+
+fn copy_metadata(file: &File, metadata: &Metadata, preserve_timestamps: bool) -> io::Result<()> {
+    file.set_permissions(metadata.permissions())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Only root may give a file away, so a failure here is expected and ignored
+        let _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+    }
+
+    if preserve_timestamps {
+        let times = FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        file.set_times(times)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write_replaces_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "old content").unwrap();
+
+        write_file_atomic(&path, &ReplacementConfig::new(), None, |w| w.write_all(b"new")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_atomic_write_keeps_original_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "original").unwrap();
+
+        let result = write_file_atomic(&path, &ReplacementConfig::new(), None, |w| {
+            w.write_all(b"partial")?;
+            Err(io::Error::other("disk full"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_write_preserves_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "echo old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options().write(true).open(&path).unwrap()
+            .set_times(FileTimes::new().set_modified(modified)).unwrap();
+
+        let config = ReplacementConfig::new().with_preserve_timestamps(true);
+        write_file_atomic(&path, &config, None, |w| w.write_all(b"echo new")).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        assert_eq!(metadata.modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_write_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_file_atomic(&link, &ReplacementConfig::new(), None, |w| w.write_all(b"new")).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use crate::core::config::{BackupMode, ReplacementConfig};
+
+/// Preserve `target` before it gets overwritten, according to the configured backup mode
+///
+/// Returns the path of the backup, if one was made. The backup is a hard link to the original when
+/// possible (the original is then replaced by a rename, so the link keeps the old content at no
+/// cost), and a copy otherwise, e.g. when the backup directory is on another file system.
+pub fn create_backup(target: &Path, config: &ReplacementConfig) -> io::Result<Option<PathBuf>> {
+    if config.backup == BackupMode::None {
+        return Ok(None);
+    }
+
+    let backup_path = backup_path(target, config)?;
+    if let Some(parent) = backup_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::remove_file(&backup_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if fs::hard_link(target, &backup_path).is_err() {
+        fs::copy(target, &backup_path)?;
+    }
+
+    Ok(Some(backup_path))
+}
+
+/// Path of the backup for `target`: next to it, or mirrored under the backup directory
+fn backup_path(target: &Path, config: &ReplacementConfig) -> io::Result<PathBuf> {
+    let file_name = target.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot back up a path without a file name"))?;
+
+    let dir = match &config.backup_dir {
+        Some(backup_dir) => {
+            let parent = target.parent().unwrap_or(Path::new(""));
+            backup_dir.join(mirrored_path(parent)?)
+        }
+        None => target.parent().unwrap_or(Path::new("")).to_path_buf(),
+    };
+
+    let mut name = file_name.to_os_string();
+    match &config.backup {
+        BackupMode::None => {}
+        BackupMode::Simple(suffix) => name.push(suffix),
+        BackupMode::Numbered => {
+            let next = highest_backup_number(&dir, &file_name.to_string_lossy())? + 1;
+            name.push(format!(".~{}~", next));
+        }
+        BackupMode::Existing(suffix) => match highest_backup_number(&dir, &file_name.to_string_lossy())? {
+            0 => name.push(suffix),
+            highest => name.push(format!(".~{}~", highest + 1)),
+        },
+    }
+
+    Ok(dir.join(name))
+}
+
+/// Relative path under which a directory is mirrored inside the backup directory, so that files
+/// with the same name in different directories never share a backup
+fn mirrored_path(dir: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(dir)?;
+    let current_dir = std::env::current_dir()?;
+    let relative = absolute.strip_prefix(&current_dir).unwrap_or(&absolute);
+
+    Ok(relative.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect())
+}
+
+fn highest_backup_number(dir: &Path, file_name: &str) -> io::Result<u64> {
+    let prefix = format!("{}.~", file_name);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut highest = 0;
+    for entry in entries {
+        let name = entry?.file_name();
+        let number = name.to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|rest| rest.strip_suffix('~'))
+            .and_then(|number| number.parse::<u64>().ok());
+        if let Some(number) = number {
+            highest = highest.max(number);
+        }
+    }
+    Ok(highest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::atomic::write_file_atomic;
+
+    #[test]
+    fn test_simple_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+        fs::write(&path, "v1").unwrap();
+
+        let config = ReplacementConfig::new().with_backup(BackupMode::Simple(".bak".to_string()));
+        write_file_atomic(&path, &config, None, |w| w.write_all(b"v2")).unwrap();
+        write_file_atomic(&path, &config, None, |w| w.write_all(b"v3")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v3");
+        assert_eq!(fs::read_to_string(dir.path().join("app.conf.bak")).unwrap(), "v2");
+    }
+
+    #[test]
+    fn test_numbered_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+        fs::write(&path, "v1").unwrap();
+
+        let config = ReplacementConfig::new().with_backup(BackupMode::Numbered);
+        for content in ["v2", "v3"] {
+            write_file_atomic(&path, &config, None, |w| w.write_all(content.as_bytes())).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(dir.path().join("app.conf.~1~")).unwrap(), "v1");
+        assert_eq!(fs::read_to_string(dir.path().join("app.conf.~2~")).unwrap(), "v2");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v3");
+    }
+
+    #[test]
+    fn test_existing_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let numbered = dir.path().join("numbered.conf");
+        let simple = dir.path().join("simple.conf");
+        fs::write(&numbered, "v1").unwrap();
+        fs::write(dir.path().join("numbered.conf.~3~"), "v0").unwrap();
+        fs::write(&simple, "v1").unwrap();
+
+        // Numbered for a file that has numbered backups already, simple otherwise
+        let config = ReplacementConfig::new().with_backup(BackupMode::Existing("~".to_string()));
+        assert_eq!(create_backup(&numbered, &config).unwrap().unwrap(), dir.path().join("numbered.conf.~4~"));
+        assert_eq!(create_backup(&simple, &config).unwrap().unwrap(), dir.path().join("simple.conf~"));
+        assert_eq!(fs::read_to_string(dir.path().join("simple.conf~")).unwrap(), "v1");
+    }
+
+    #[test]
+    fn test_backup_dir_mirrors_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        for sub in ["a", "b"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+            fs::write(dir.path().join(sub).join("mod.rs"), sub).unwrap();
+        }
+
+        let config = ReplacementConfig::new()
+            .with_backup(BackupMode::Simple("~".to_string()))
+            .with_backup_dir(Some(backups.path().to_path_buf()));
+        let mut made = Vec::new();
+        for sub in ["a", "b"] {
+            made.push(create_backup(&dir.path().join(sub).join("mod.rs"), &config).unwrap().unwrap());
+        }
+
+        assert_ne!(made[0], made[1]);
+        assert!(made.iter().all(|path| path.starts_with(backups.path())));
+        assert_eq!(fs::read_to_string(&made[0]).unwrap(), "a");
+        assert_eq!(fs::read_to_string(&made[1]).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_no_backup_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "v1").unwrap();
+
+        write_file_atomic(&path, &ReplacementConfig::new(), None, |w| w.write_all(b"v2")).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
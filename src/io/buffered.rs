@@ -1,10 +1,23 @@
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, Read};
 use std::path::Path;
 use encoding_rs::Encoding;
-use crate::core::{ReplacementConfig, perform_replacement_parallel, perform_replacement_utf8_optimized};
+use regex::Regex;
+use super::atomic::write_file_atomic;
+use super::diff::unified_diff;
+use super::encoding::{decode_content, encode_text, looks_binary, output_format};
+use super::journal::Journal;
+use super::report::{Engine, FileReport};
+use super::streaming::process_file_by_size;
+use crate::core::bytes::{display_escaped, escape_bytes, unescape_bytes};
+use crate::error::{Error, Result, Warning, check_strict};
+use crate::core::{Occurrences, ReplacementConfig, build_configured_regex, perform_configured_replacement};
 
 /// Process file with optimized buffering and automatic algorithm selection
+///
+/// The report carries the warnings about the content, and the diff of a dry run; in strict mode
+/// the warnings fail the call instead.
 pub fn process_file_buffered(
     input_path: &Path,
     output_path: Option<&Path>,
@@ -12,8 +25,41 @@ pub fn process_file_buffered(
     replacement: &str,
     encoding: &'static Encoding,
     config: &ReplacementConfig
-) -> io::Result<(usize, std::time::Duration)> {
+) -> Result<FileReport> {
+    build_configured_regex(pattern, config)
+        .map_err(Error::invalid_input)
+        .and_then(|regex| {
+            process_file_with_encoding(input_path, output_path, pattern, replacement, regex.as_ref(), Some(encoding), config, None)
+        })
+        .map_err(|e| e.with_path(input_path))
+}
+
+/// Buffered processing with a compiled regex, where `None` means the encoding is detected from the file content.
+/// Files that look binary are left untouched when the encoding is detected, also in byte mode, as
+/// are files whose encoding is detected with less than the minimum confidence.
+/// In dry-run mode nothing is written and the rendered diff is returned in the report instead.
+/// A written file is recorded in `journal` if one is given.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn process_file_with_encoding(
+    input_path: &Path,
+    output_path: Option<&Path>,
+    pattern: &str,
+    replacement: &str,
+    regex: Option<&Regex>,
+    encoding: Option<&'static Encoding>,
+    config: &ReplacementConfig,
+    journal: Option<&Journal>
+) -> Result<FileReport> {
     let start_time = std::time::Instant::now();
+    let mut report = FileReport::new(input_path.display().to_string());
+    let config = config.for_file();
+    let config = config.as_ref();
+    // Every selected occurrence was found in earlier files
+    if config.occurrences_exhausted() {
+        report.skipped = Some("occurrences exhausted");
+        report.elapsed = start_time.elapsed();
+        return Ok(report);
+    }
     
     // Open input file with optimized buffer
     let input_file = File::open(input_path)?;
@@ -22,49 +68,118 @@ pub fn process_file_buffered(
     // Read entire content for files smaller than threshold
     let mut buffer = Vec::new();
     reader.read_to_end(&mut buffer)?;
+    report.bytes_in = buffer.len() as u64;
+    report.bytes_out = report.bytes_in;
+    
+    if encoding.is_none() && looks_binary(&buffer) {
+        report.skipped = Some("binary");
+        report.elapsed = start_time.elapsed();
+        return Ok(report);
+    }
     
-    // Decode content
-    let (content, _, _) = encoding.decode(&buffer);
+    // Decode content, detecting the encoding unless given; the byte mode reads it as UTF-8 as it is
+    let (content, encoding, had_bom, confidence) = if config.byte_mode {
+        (escape_bytes(&buffer), encoding_rs::UTF_8, false, None)
+    } else {
+        decode_content(&buffer, encoding, &mut report.warnings)
+    };
+    report.encoding = Some(encoding.name());
+    report.bom = had_bom;
+    report.confidence = confidence;
+    // Rather than guess, leave the file alone; the warning tells what the guess was
+    if config.rejects_confidence(confidence) {
+        report.skipped = Some("ambiguous encoding");
+        report.elapsed = start_time.elapsed();
+        return Ok(report);
+    }
+    check_strict(config.strict, Some(input_path), &report.warnings)?;
     let content_str = content.into_owned();
     
     // Choose optimal processing strategy
-    let (processed_content, replacement_count) = if content_str.len() > config.parallel_threshold {
-        perform_replacement_parallel(&content_str, pattern, replacement, config.parallel_threshold / 2)
+    report.engine = if content_str.len() > config.parallel_threshold { Engine::Parallel } else { Engine::Sequential };
+    let (processed_content, replacement_count) =
+        perform_configured_replacement(&content_str, pattern, replacement, regex, config);
+    report.replacements = replacement_count;
+    let (output_encoding, bom) = output_format(encoding, had_bom, config);
+    // Also when a BOM is added or removed
+    let transcoding = output_encoding != encoding || had_bom == bom.is_empty();
+    if output_encoding != encoding {
+        report.output_encoding = Some(output_encoding.name());
+    }
+    
+    // Leave unchanged files alone when modifying in place, unless they change encoding, BOM or
+    // line endings
+    if replacement_count == 0 && !transcoding && output_path.is_none() && processed_content == content_str {
+        report.elapsed = start_time.elapsed();
+        return Ok(report);
+    }
+    
+    // Encode and write through a temporary file renamed over the target
+    let (encoded_content, had_encoding_errors) = if config.byte_mode {
+        (unescape_bytes(&processed_content), false)
     } else {
-        perform_replacement_utf8_optimized(&content_str, pattern, replacement, config.enable_caching)
+        encode_text(output_encoding, &processed_content)
     };
+    if had_encoding_errors {
+        report.warnings.push(Warning::UnencodableCharacters { encoding: output_encoding.name() });
+        check_strict(config.strict, Some(input_path), &report.warnings)?;
+    }
+    report.bytes_out = (bom.len() + encoded_content.len()) as u64;
     
-    // Write output
     let output_path = output_path.unwrap_or(input_path);
-    let output_file = File::create(output_path)?;
-    let mut writer = BufWriter::with_capacity(config.buffer_size, output_file);
+    if config.dry_run {
+        let display = |text| if config.byte_mode { display_escaped(text) } else { Cow::Borrowed(text) };
+        let diff = unified_diff(
+            &display(&content_str),
+            &display(&processed_content),
+            &input_path.display().to_string(),
+            &output_path.display().to_string(),
+            config.diff_context,
+            config.diff_color
+        );
+        report.diff = Some(diff).filter(|diff| !diff.is_empty());
+        report.elapsed = start_time.elapsed();
+        return Ok(report);
+    }
     
-    // Encode and write
-    let (encoded_content, _, _) = encoding.encode(&processed_content);
-    writer.write_all(&encoded_content)?;
-    writer.flush()?;
+    write_file_atomic(output_path, config, journal, |writer| {
+        writer.write_all(bom)?;
+        writer.write_all(&encoded_content)
+    })?;
     
-    let elapsed = start_time.elapsed();
-    Ok((replacement_count, elapsed))
+    report.elapsed = start_time.elapsed();
+    Ok(report)
 }
 
 /// Process multiple files in parallel
+///
+/// With `encoding` set to `None`, each file's encoding is detected separately and binary files are skipped.
+/// Like `process_file_auto`, files larger than ten times the buffer size are streamed.
+/// The pattern is compiled once, and an invalid one fails the whole run. Otherwise a result is
+/// returned for every file, in input order: a file that fails, e.g. in strict mode on its first
+/// warning, is left untouched without stopping the others. The reports carry the diffs of a dry
+/// run for the caller to print. Every file written is recorded in `journal` if one is given.
 pub fn process_files_parallel(
     file_paths: &[&Path],
     pattern: &str,
     replacement: &str,
-    encoding: &'static Encoding,
-    config: &ReplacementConfig
-) -> io::Result<Vec<(String, usize, std::time::Duration)>> {
+    encoding: Option<&'static Encoding>,
+    config: &ReplacementConfig,
+    journal: Option<&Journal>
+) -> Result<Vec<Result<FileReport>>> {
     use rayon::prelude::*;
     
-    file_paths
-        .par_iter()
-        .map(|&path| {
-            let result = process_file_buffered(path, None, pattern, replacement, encoding, config)?;
-            Ok((path.display().to_string(), result.0, result.1))
-        })
-        .collect()
+    let regex = build_configured_regex(pattern, config).map_err(Error::invalid_input)?;
+    let process = |&path: &&Path| {
+        process_file_by_size(path, None, pattern, replacement, regex.as_ref(), encoding, config, journal)
+            .map_err(|e| e.with_path(path))
+    };
+    Ok(if config.occurrences.as_ref().is_some_and(Occurrences::is_global) {
+        // Occurrences numbered across files are numbered in the order of the files
+        file_paths.iter().map(process).collect()
+    } else {
+        file_paths.par_iter().map(process).collect()
+    })
 }
 
 #[cfg(test)]
@@ -89,7 +204,7 @@ mod tests {
             &config
         ).unwrap();
         
-        assert_eq!(result.0, 3);
+        assert_eq!(result.replacements, 3);
         
         // Verify file content
         let mut content = String::new();
@@ -97,4 +212,190 @@ mod tests {
         file.read_to_string(&mut content).unwrap();
         assert_eq!(content, "TEST TEST TEST");
     }
+
+    #[test]
+    fn test_files_parallel_detects_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let utf8_bom = dir.path().join("bom.txt");
+        let latin1 = dir.path().join("latin1.txt");
+        let binary = dir.path().join("image.bin");
+        std::fs::write(&utf8_bom, b"\xEF\xBB\xBFcaf\xC3\xA9 test").unwrap();
+        std::fs::write(&latin1, b"caf\xE9 test").unwrap();
+        std::fs::write(&binary, b"test\0test").unwrap();
+
+        let paths = [utf8_bom.as_path(), latin1.as_path(), binary.as_path()];
+        let results: Vec<FileReport> = process_files_parallel(&paths, "test", "TEST", None, &ReplacementConfig::new(), None)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let counts: Vec<usize> = results.iter().map(|r| r.replacements).collect();
+        assert_eq!(counts, [1, 1, 0]);
+        let encodings: Vec<Option<&str>> = results.iter().map(|r| r.encoding).collect();
+        assert_eq!(encodings, [Some("UTF-8"), Some("windows-1252"), None]);
+        assert!(results[0].bom);
+        assert_eq!(results[1].warnings.len(), 1);
+        assert_eq!(results[2].skipped, Some("binary"));
+
+        assert_eq!(std::fs::read(&utf8_bom).unwrap(), b"\xEF\xBB\xBFcaf\xC3\xA9 TEST");
+        assert_eq!(std::fs::read(&latin1).unwrap(), b"caf\xE9 TEST");
+        assert_eq!(std::fs::read(&binary).unwrap(), b"test\0test");
+    }
+
+    #[test]
+    fn test_files_parallel_streams_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.txt");
+        let large = dir.path().join("large.txt");
+        std::fs::write(&small, "test\n").unwrap();
+        std::fs::write(&large, "test\n".repeat(100)).unwrap();
+
+        // Over ten times the buffer size, a file is streamed as it would be alone
+        let paths = [small.as_path(), large.as_path()];
+        let config = ReplacementConfig::new().with_buffer_size(32);
+        let results: Vec<FileReport> =
+            process_files_parallel(&paths, "test", "TEST", None, &config, None).unwrap().into_iter().map(Result::unwrap).collect();
+        let engines: Vec<Engine> = results.iter().map(|r| r.engine).collect();
+        assert_eq!(engines, [Engine::Sequential, Engine::Streaming]);
+        assert_eq!(results[1].replacements, 100);
+        assert_eq!(std::fs::read_to_string(&large).unwrap(), "TEST\n".repeat(100));
+    }
+
+    #[test]
+    fn test_dry_run_leaves_file_untouched() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"test\nkeep\n").unwrap();
+        temp_file.flush().unwrap();
+
+        let config = ReplacementConfig::new().with_dry_run(true);
+        let paths = [temp_file.path()];
+        let report = process_files_parallel(&paths, "test", "TEST", None, &config, None).unwrap().remove(0).unwrap();
+
+        assert_eq!(report.replacements, 1);
+        assert!(report.diff.unwrap().contains("-test\n+TEST\n"));
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"test\nkeep\n");
+    }
+
+    #[test]
+    fn test_strict_mode_fails_on_warnings() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"caf\xE9 test").unwrap();
+        temp_file.flush().unwrap();
+
+        let utf8 = NamedTempFile::new().unwrap();
+        std::fs::write(utf8.path(), "test").unwrap();
+
+        // The failing file does not stop the others
+        let paths = [temp_file.path(), utf8.path()];
+        let config = ReplacementConfig::new().with_strict(true);
+        let mut results = process_files_parallel(&paths, "test", "TEST", None, &config, None).unwrap().into_iter();
+        let error = results.next().unwrap().unwrap_err();
+        assert!(matches!(error, crate::Error::Warning { path: Some(_), warning: Warning::GuessedEncoding { .. } }));
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"caf\xE9 test");
+        assert_eq!(results.next().unwrap().unwrap().replacements, 1);
+        assert_eq!(std::fs::read_to_string(utf8.path()).unwrap(), "TEST");
+
+        // A replacement the encoding cannot represent is caught before writing
+        let config = ReplacementConfig::new().with_strict(true);
+        let error = process_file_buffered(temp_file.path(), None, "test", "✓", encoding_rs::WINDOWS_1252, &config).unwrap_err();
+        assert!(matches!(error, crate::Error::Warning { warning: Warning::UnencodableCharacters { .. }, .. }));
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"caf\xE9 test");
+    }
+
+    #[test]
+    fn test_min_confidence_skips_ambiguous_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let ambiguous = dir.path().join("latin1.txt");
+        let russian = dir.path().join("koi8.txt");
+        std::fs::write(&ambiguous, b"caf\xE9 test").unwrap();
+        let (text, _, _) = encoding_rs::KOI8_R.encode("Определение кодировки русского текста: test");
+        std::fs::write(&russian, &text).unwrap();
+
+        let paths = [ambiguous.as_path(), russian.as_path()];
+        let config = ReplacementConfig::new().with_min_confidence(Some(0.5));
+        let results: Vec<FileReport> =
+            process_files_parallel(&paths, "test", "TEST", None, &config, None).unwrap().into_iter().map(Result::unwrap).collect();
+        assert_eq!(results[0].skipped, Some("ambiguous encoding"));
+        assert!(results[0].confidence.unwrap() < 0.5);
+        assert_eq!(results[1].replacements, 1);
+        assert!(results[1].confidence.unwrap() >= 0.5);
+        assert_eq!(std::fs::read(&ambiguous).unwrap(), b"caf\xE9 test");
+    }
+
+    #[test]
+    fn test_transcoding_without_replacements() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"caf\xE9 test").unwrap();
+        temp_file.flush().unwrap();
+
+        // A file changing encoding is written even without a match
+        let config = ReplacementConfig::new()
+            .with_output_encoding(Some(encoding_rs::UTF_8))
+            .with_bom(crate::core::BomMode::Add);
+        let report = process_file_buffered(temp_file.path(), None, "none", "", encoding_rs::WINDOWS_1252, &config).unwrap();
+        assert_eq!((report.replacements, report.output_encoding), (0, Some("UTF-8")));
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), "\u{FEFF}café test".as_bytes());
+
+        // Read back with its BOM, even as another encoding is given, it can lose the BOM alone
+        let config = ReplacementConfig::new().with_bom(crate::core::BomMode::Remove);
+        let report = process_file_buffered(temp_file.path(), None, "none", "", encoding_rs::WINDOWS_1252, &config).unwrap();
+        assert_eq!((report.encoding, report.bom, report.output_encoding), (Some("UTF-8"), true, None));
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), "café test".as_bytes());
+    }
+
+    #[test]
+    fn test_eol_conversion_without_replacements() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"one\ntwo\r\nthree\n").unwrap();
+        temp_file.flush().unwrap();
+
+        let config = ReplacementConfig::new().with_eol(crate::core::EolMode::Crlf);
+        let report = process_file_buffered(temp_file.path(), None, "none", "", encoding_rs::UTF_8, &config).unwrap();
+        assert_eq!(report.replacements, 0);
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"one\r\ntwo\r\nthree\r\n");
+
+        // A pattern spanning lines matches across CRLF, and the replacement takes its line ending
+        let config = ReplacementConfig::new().with_eol_agnostic(true);
+        let report = process_file_buffered(temp_file.path(), None, "one\ntwo", "1\n2", encoding_rs::UTF_8, &config).unwrap();
+        assert_eq!(report.replacements, 1);
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"1\r\n2\r\nthree\r\n");
+    }
+
+    #[test]
+    fn test_eol_agnostic_keeps_mixed_line_endings() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"one\r\ntwo\nthree\r\n").unwrap();
+        temp_file.flush().unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH;
+        temp_file.as_file().set_modified(modified).unwrap();
+
+        // Without a match, the file is left alone
+        let config = ReplacementConfig::new().with_eol_agnostic(true);
+        let report = process_file_buffered(temp_file.path(), None, "none", "", encoding_rs::UTF_8, &config).unwrap();
+        assert_eq!(report.replacements, 0);
+        assert_eq!(std::fs::metadata(temp_file.path()).unwrap().modified().unwrap(), modified);
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"one\r\ntwo\nthree\r\n");
+
+        // Line endings outside the match stay as they were; those of the replacement follow the match's
+        let report = process_file_buffered(temp_file.path(), None, "two\nthree", "2\n3", encoding_rs::UTF_8, &config).unwrap();
+        assert_eq!(report.replacements, 1);
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"one\r\n2\n3\r\n");
+
+        let report = process_file_buffered(temp_file.path(), None, r"(\d)\n", "$1!\n", encoding_rs::UTF_8, &config.with_regex(true)).unwrap();
+        assert_eq!(report.replacements, 2);
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"one\r\n2!\n3!\r\n");
+    }
+
+    #[test]
+    fn test_byte_mode_keeps_invalid_bytes() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"\xEF\xBB\xBFna\xEFve test, na\xC3\xAFve test\n").unwrap();
+        temp_file.flush().unwrap();
+
+        let config = ReplacementConfig::new().with_byte_mode(true);
+        let report = process_file_buffered(temp_file.path(), None, "test", "✓", encoding_rs::UTF_8, &config).unwrap();
+        assert_eq!(report.replacements, 2);
+        assert!(report.warnings.is_empty());
+        assert_eq!(std::fs::read(temp_file.path()).unwrap(), b"\xEF\xBB\xBFna\xEFve \xE2\x9C\x93, na\xC3\xAFve \xE2\x9C\x93\n");
+    }
 }
@@ -0,0 +1,86 @@
+use similar::TextDiff;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Render a unified diff between the original and the replaced content.
+/// Returns an empty string when both are identical.
+pub fn unified_diff(original: &str, replaced: &str, old_label: &str, new_label: &str, context: usize, color: bool) -> String {
+    if original == replaced {
+        return String::new();
+    }
+
+    let diff = TextDiff::from_lines(original, replaced);
+    let rendered = diff
+        .unified_diff()
+        .context_radius(context)
+        .header(old_label, new_label)
+        .to_string();
+
+    if color {
+        colorize(&rendered)
+    } else {
+        rendered
+    }
+}
+
+fn colorize(diff: &str) -> String {
+    let mut result = String::with_capacity(diff.len() + diff.len() / 8);
+
+    for line in diff.split_inclusive('\n') {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        let style = if text.starts_with("---") || text.starts_with("+++") {
+            BOLD
+        } else if text.starts_with("@@") {
+            CYAN
+        } else if text.starts_with('-') {
+            RED
+        } else if text.starts_with('+') {
+            GREEN
+        } else {
+            ""
+        };
+
+        if style.is_empty() {
+            result.push_str(line);
+        } else {
+            result.push_str(style);
+            result.push_str(text);
+            result.push_str(RESET);
+            result.push_str(newline);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let original = "one\ntwo\nthree\nfour\n";
+        let replaced = "one\n2\nthree\nfour\n";
+        let diff = unified_diff(original, replaced, "a.txt", "a.txt", 1, false);
+        assert_eq!(diff, "--- a.txt\n+++ a.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n");
+    }
+
+    #[test]
+    fn test_unified_diff_no_changes() {
+        assert_eq!(unified_diff("same\n", "same\n", "a", "b", 3, true), "");
+    }
+
+    #[test]
+    fn test_unified_diff_color() {
+        let diff = unified_diff("a\n", "b\n", "x", "x", 0, true);
+        assert!(diff.contains("\x1b[31m-a\x1b[0m\n"));
+        assert!(diff.contains("\x1b[32m+b\x1b[0m\n"));
+    }
+}
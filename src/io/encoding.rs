@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+use encoding_rs::Encoding;
+use crate::core::{BomMode, ReplacementConfig};
+use crate::error::Warning;
+
+/// How many leading bytes are inspected when looking for binary content
+const BINARY_SNIFF_LEN: usize = 8192;
+
+/// How many leading bytes the statistical detection looks at
+pub(crate) const DETECTION_SAMPLE_LEN: usize = 64 * 1024;
+
+/// Non-ASCII bytes a statistical guess needs to reach a confidence of one half
+const HALF_CONFIDENCE_BYTES: f32 = 8.0;
+
+/// Detect the encoding of raw file content: BOM first, then BOM-less UTF-16, then UTF-8, then a
+/// statistical guess among the legacy encodings (Shift_JIS, EUC-KR, GB18030, KOI8-R, ISO-8859-x,
+/// Windows code pages...).
+///
+/// Returns the encoding, whether it was taken from a BOM and the confidence of the detection,
+/// from 0 to 1: a BOM or valid UTF-8 is certain, while a guess grows more confident with the
+/// number of non-ASCII bytes it rests on.
+pub fn detect_encoding(buffer: &[u8]) -> (&'static Encoding, bool, f32) {
+    detect_head_encoding(buffer, true)
+}
+
+/// `detect_encoding` on the first bytes of a stream, `complete` unless more follow
+///
+/// Valid UTF-8 may then end in the middle of a character cut off by the end of the sample.
+pub(crate) fn detect_head_encoding(buffer: &[u8], complete: bool) -> (&'static Encoding, bool, f32) {
+    if let Some((encoding, _bom_length)) = Encoding::for_bom(buffer) {
+        return (encoding, true, 1.0);
+    }
+
+    // ASCII in UTF-16 is valid UTF-8 too, full of NUL characters
+    if let Some((encoding, confidence)) = detect_utf16(buffer) {
+        return (encoding, false, confidence);
+    }
+    match std::str::from_utf8(buffer) {
+        Ok(_) => return (encoding_rs::UTF_8, false, 1.0),
+        Err(e) if !complete && e.error_len().is_none() => return (encoding_rs::UTF_8, false, 1.0),
+        Err(_) => {}
+    }
+
+    let sample = &buffer[..buffer.len().min(DETECTION_SAMPLE_LEN)];
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(sample, complete && sample.len() == buffer.len());
+    let (encoding, beat_others) = detector.guess_assess(None, false);
+    let non_ascii = sample.iter().filter(|byte| !byte.is_ascii()).count() as f32;
+    let confidence = non_ascii / (non_ascii + HALF_CONFIDENCE_BYTES);
+    // No candidate scored better than another: the guess is little more than a default
+    (encoding, false, if beat_others { confidence } else { confidence / 2.0 })
+}
+
+/// BOM-less UTF-16 of mostly Latin text, recognized by the zero byte of most of its code units
+///
+/// Returns the byte order and the share of code units with that zero byte. A NUL character, zero
+/// bytes on both sides or an odd length rule UTF-16 out.
+fn detect_utf16(buffer: &[u8]) -> Option<(&'static Encoding, f32)> {
+    if buffer.is_empty() || !buffer.len().is_multiple_of(2) {
+        return None;
+    }
+    let sample = &buffer[..buffer.len().min(BINARY_SNIFF_LEN)];
+    let (mut low_zero, mut high_zero) = (0usize, 0usize);
+    for unit in sample.chunks_exact(2) {
+        match (unit[0], unit[1]) {
+            (0, 0) => return None,
+            (_, 0) => high_zero += 1,
+            (0, _) => low_zero += 1,
+            _ => {}
+        }
+    }
+    let units = sample.len() / 2;
+    let (encoding, fitting, other) = if high_zero >= low_zero {
+        (encoding_rs::UTF_16LE, high_zero, low_zero)
+    } else {
+        (encoding_rs::UTF_16BE, low_zero, high_zero)
+    };
+    (fitting * 2 >= units && other * 20 <= units).then(|| (encoding, fitting as f32 / units as f32))
+}
+
+/// Decode raw content with `encoding`, or with the detected encoding when it is `None`
+///
+/// Returns the text, the encoding, whether it was taken from a BOM and the confidence of the
+/// detection (`None` for a given encoding). A guessed encoding and undecodable bytes are
+/// reported in `warnings`.
+pub fn decode_content<'a>(
+    buffer: &'a [u8],
+    encoding: Option<&'static Encoding>,
+    warnings: &mut Vec<Warning>
+) -> (Cow<'a, str>, &'static Encoding, bool, Option<f32>) {
+    let (encoding, had_bom, confidence) = match encoding {
+        // A BOM overrides the given encoding, as it does when decoding
+        Some(encoding) => match Encoding::for_bom(buffer) {
+            Some((bom_encoding, _)) => (bom_encoding, true, None),
+            None => (encoding, false, None),
+        },
+        None => {
+            let (detected, had_bom, confidence) = detect_encoding(buffer);
+            if confidence < 1.0 {
+                warnings.push(Warning::GuessedEncoding { encoding: detected.name() });
+            }
+            (detected, had_bom, Some(confidence))
+        }
+    };
+
+    let (content, _, had_errors) = encoding.decode(buffer);
+    if had_errors {
+        warnings.push(Warning::DecodeErrors { encoding: encoding.name() });
+    }
+    (content, encoding, had_bom, confidence)
+}
+
+/// Byte order mark written back in front of re-encoded content
+pub fn bom_bytes(encoding: &'static Encoding) -> &'static [u8] {
+    match encoding.name() {
+        "UTF-8" => &[0xEF, 0xBB, 0xBF],
+        "UTF-16LE" => &[0xFF, 0xFE],
+        "UTF-16BE" => &[0xFE, 0xFF],
+        _ => &[],
+    }
+}
+
+/// The encoding to write content read in `encoding` in, and the BOM to write in front of it
+///
+/// The output encoding and BOM mode of `config` apply, except in byte mode, where the bytes are
+/// written back as they were read.
+pub fn output_format(
+    encoding: &'static Encoding,
+    had_bom: bool,
+    config: &ReplacementConfig
+) -> (&'static Encoding, &'static [u8]) {
+    if config.byte_mode {
+        return (encoding, &[]);
+    }
+    let output = config.output_encoding.unwrap_or(encoding);
+    let bom = match config.bom {
+        BomMode::Keep => had_bom,
+        BomMode::Add => true,
+        BomMode::Remove => false,
+    };
+    (output, if bom { bom_bytes(output) } else { &[] })
+}
+
+/// Encode text like `Encoding::encode`, except that UTF-16 is written as UTF-16 rather than UTF-8
+///
+/// Returns the bytes and whether some characters cannot be represented in the encoding and were
+/// written as HTML numeric character references.
+pub fn encode_text<'a>(encoding: &'static Encoding, text: &'a str) -> (Cow<'a, [u8]>, bool) {
+    if encoding == encoding_rs::UTF_16LE {
+        (Cow::Owned(text.encode_utf16().flat_map(u16::to_le_bytes).collect()), false)
+    } else if encoding == encoding_rs::UTF_16BE {
+        (Cow::Owned(text.encode_utf16().flat_map(u16::to_be_bytes).collect()), false)
+    } else {
+        let (bytes, _, had_errors) = encoding.encode(text);
+        (bytes, had_errors)
+    }
+}
+
+/// Whether content looks like a binary file (a NUL byte near the start, and neither a UTF-16 BOM
+/// nor the zero bytes of BOM-less UTF-16)
+pub fn looks_binary(buffer: &[u8]) -> bool {
+    if Encoding::for_bom(buffer).is_some() || detect_utf16(buffer).is_some() {
+        return false;
+    }
+    buffer[..buffer.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"plain text"), (encoding_rs::UTF_8, false, 1.0));
+        assert_eq!(detect_encoding(&[0xEF, 0xBB, 0xBF, b'a']), (encoding_rs::UTF_8, true, 1.0));
+        assert_eq!(detect_encoding(&[0xFF, 0xFE, b'a', 0]), (encoding_rs::UTF_16LE, true, 1.0));
+        assert_eq!(detect_encoding(b"caf\xe9").0, encoding_rs::WINDOWS_1252);
+        // A character cut off at the end of a stream's head is still UTF-8
+        assert_eq!(detect_head_encoding("café".as_bytes()[..4].as_ref(), false).0, encoding_rs::UTF_8);
+    }
+
+    #[test]
+    fn test_detect_legacy_encodings() {
+        let samples = [
+            (encoding_rs::SHIFT_JIS, "日本語のテキストを検出します。文字コードの判定は難しいです。"),
+            (encoding_rs::EUC_KR, "한국어 텍스트의 인코딩을 감지합니다. 문자 인코딩 판별은 어렵습니다."),
+            (encoding_rs::GBK, "检测中文文本的编码。字符编码的判断很困难，但是我们可以试试。"),
+            (encoding_rs::KOI8_R, "Определение кодировки русского текста. Это довольно сложная задача."),
+            (encoding_rs::ISO_8859_7, "Ανίχνευση της κωδικοποίησης ελληνικού κειμένου. Είναι δύσκολο."),
+        ];
+        for (encoding, text) in samples {
+            let (raw, _, _) = encoding.encode(text);
+            let (detected, had_bom, confidence) = detect_encoding(&raw);
+            // A superset such as KOI8-U for KOI8-R decodes the text all the same
+            assert_eq!(detected.decode_without_bom_handling(&raw).0, text, "{}", encoding.name());
+            assert!(!had_bom);
+            assert!(confidence > 0.8, "{} {}", encoding.name(), confidence);
+        }
+
+        // A single accented letter is a weak clue
+        assert!(detect_encoding(b"caf\xe9").2 < 0.5);
+    }
+
+    #[test]
+    fn test_detect_utf16_without_bom() {
+        let (raw, _) = encode_text(encoding_rs::UTF_16LE, "plain text, with a few accents: é à ü");
+        assert_eq!(detect_encoding(&raw).0, encoding_rs::UTF_16LE);
+        assert!(!looks_binary(&raw));
+        let (raw, _) = encode_text(encoding_rs::UTF_16BE, "plain text");
+        assert_eq!(detect_encoding(&raw), (encoding_rs::UTF_16BE, false, 1.0));
+    }
+
+    #[test]
+    fn test_output_format() {
+        let config = ReplacementConfig::new();
+        assert_eq!(output_format(encoding_rs::UTF_8, true, &config), (encoding_rs::UTF_8, &[0xEF, 0xBB, 0xBF][..]));
+        let config = config.with_output_encoding(Some(encoding_rs::UTF_16LE));
+        assert_eq!(output_format(encoding_rs::UTF_8, true, &config), (encoding_rs::UTF_16LE, &[0xFF, 0xFE][..]));
+        let config = config.with_bom(BomMode::Remove);
+        assert_eq!(output_format(encoding_rs::UTF_8, true, &config).1, b"");
+        // Legacy encodings have no BOM to add
+        let config = ReplacementConfig::new().with_bom(BomMode::Add);
+        assert_eq!(output_format(encoding_rs::WINDOWS_1252, false, &config), (encoding_rs::WINDOWS_1252, &[][..]));
+    }
+
+    #[test]
+    fn test_encode_text() {
+        assert_eq!(encode_text(encoding_rs::UTF_16LE, "aé").0.as_ref(), [b'a', 0, 0xE9, 0]);
+        assert_eq!(encode_text(encoding_rs::UTF_16BE, "😀").0.as_ref(), [0xD8, 0x3D, 0xDE, 0x00]);
+        assert_eq!(encode_text(encoding_rs::WINDOWS_1252, "é✓"), (Cow::Owned(b"\xE9&#10003;".to_vec()), true));
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(looks_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!looks_binary(b"plain text"));
+        assert!(!looks_binary(&[0xFF, 0xFE, b'a', 0]));
+    }
+}
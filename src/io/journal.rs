@@ -0,0 +1,451 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::core::ReplacementConfig;
+use super::atomic::write_file_atomic;
+
+/// Number of most recent runs kept in the journal
+const MAX_KEPT_RUNS: usize = 20;
+const MANIFEST_NAME: &str = "manifest.ndjson";
+
+/// Record of the files modified by one run, so that the run can be undone
+///
+/// Each run gets a directory under `<state dir>/runs/<run id>/` holding a copy of every original
+/// (a hard link when possible, since the original is replaced by a rename) and a manifest with one
+/// JSON line per file, appended as soon as the file has been replaced.
+#[derive(Debug)]
+pub struct Journal {
+    run_id: String,
+    dir: PathBuf,
+    next_index: AtomicUsize,
+    manifest: Mutex<File>,
+}
+
+/// One modified file in a run's manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    /// Name of the saved original in the run's directory; `None` if the run created the file
+    pub saved: Option<String>,
+    pub original_sha256: Option<String>,
+    pub new_sha256: String,
+}
+
+/// Original content saved before a file is replaced, waiting for the replacement to succeed
+#[derive(Debug)]
+pub struct StagedOriginal {
+    saved: Option<String>,
+    original_sha256: Option<String>,
+}
+
+/// Summary of a journaled run
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub files: usize,
+}
+
+/// Files put back by an undo
+#[derive(Debug, Clone, Default)]
+pub struct UndoOutcome {
+    pub run_id: String,
+    pub restored: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl Journal {
+    /// Start a new run journal in the default state directory
+    pub fn create() -> io::Result<Journal> {
+        let state_dir = state_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory: set XDG_STATE_HOME or HOME"))?;
+        Self::create_in(&state_dir)
+    }
+
+    /// Start a new run journal under `state_dir`
+    pub fn create_in(state_dir: &Path) -> io::Result<Journal> {
+        let runs = state_dir.join("runs");
+        fs::create_dir_all(&runs)?;
+
+        let base_id = new_run_id();
+        let mut run_id = base_id.clone();
+        let mut attempt = 1;
+        let dir = loop {
+            let dir = runs.join(&run_id);
+            match fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    attempt += 1;
+                    run_id = format!("{}.{}", base_id, attempt);
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let manifest = OpenOptions::new().create(true).append(true).open(dir.join(MANIFEST_NAME))?;
+        Ok(Journal {
+            run_id,
+            dir,
+            next_index: AtomicUsize::new(0),
+            manifest: Mutex::new(manifest),
+        })
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Save the current content of `target`, which is about to be replaced
+    pub fn stage(&self, target: &Path) -> io::Result<StagedOriginal> {
+        if !target.exists() {
+            return Ok(StagedOriginal { saved: None, original_sha256: None });
+        }
+
+        let saved = format!("{}.orig", self.next_index.fetch_add(1, Ordering::Relaxed));
+        let saved_path = self.dir.join(&saved);
+        if fs::hard_link(target, &saved_path).is_err() {
+            fs::copy(target, &saved_path)?;
+        }
+
+        Ok(StagedOriginal {
+            saved: Some(saved),
+            original_sha256: Some(sha256_file(&saved_path)?),
+        })
+    }
+
+    /// Record that `target` now holds content with the given checksum
+    pub fn commit(&self, target: &Path, staged: StagedOriginal, new_sha256: String) -> io::Result<()> {
+        let entry = JournalEntry {
+            path: std::path::absolute(target)?,
+            saved: staged.saved,
+            original_sha256: staged.original_sha256,
+            new_sha256,
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::other)?;
+        line.push(b'\n');
+
+        let mut manifest = self.manifest.lock().unwrap_or_else(|e| e.into_inner());
+        manifest.write_all(&line)?;
+        manifest.sync_data()
+    }
+
+    /// Close the run: an empty run is discarded, and only the most recent runs are kept
+    ///
+    /// Returns whether this run was kept, i.e. whether it modified any file.
+    pub fn finish(self) -> io::Result<bool> {
+        let runs_dir = self.dir.parent().map(Path::to_path_buf);
+        let kept = !read_manifest(&self.dir)?.is_empty();
+        if !kept {
+            fs::remove_dir_all(&self.dir)?;
+        }
+
+        if let Some(runs_dir) = runs_dir {
+            let runs = run_ids(&runs_dir)?;
+            for run_id in runs.iter().take(runs.len().saturating_sub(MAX_KEPT_RUNS)) {
+                fs::remove_dir_all(runs_dir.join(run_id))?;
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// Default journal location: `$XDG_STATE_HOME/replacer`, falling back to `~/.local/state/replacer`
+/// (`%LOCALAPPDATA%\replacer` on Windows)
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join("replacer"));
+    }
+    if cfg!(windows) && let Some(dir) = std::env::var_os("LOCALAPPDATA") {
+        return Some(PathBuf::from(dir).join("replacer"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/replacer"))
+}
+
+/// Journaled runs, oldest first
+pub fn list_runs(state_dir: &Path) -> io::Result<Vec<RunSummary>> {
+    let runs_dir = state_dir.join("runs");
+    run_ids(&runs_dir)?
+        .into_iter()
+        .map(|run_id| {
+            let files = read_manifest(&runs_dir.join(&run_id))?.len();
+            Ok(RunSummary { run_id, files })
+        })
+        .collect()
+}
+
+/// Restore the files modified by a run (by default the latest one that modified any) and drop it
+/// from the journal
+///
+/// Refuses to do anything if a file changed since the run, unless `force` is set.
+pub fn undo_run(state_dir: &Path, run_id: Option<&str>, force: bool) -> io::Result<UndoOutcome> {
+    let runs_dir = state_dir.join("runs");
+    let mut known = run_ids(&runs_dir)?;
+    let run_id = match run_id {
+        // Only the name of an existing run, never a path such as `..` resolving outside of it
+        Some(run_id) => {
+            if matches!(run_id, "" | "." | "..") || !known.iter().any(|id| id == run_id) {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("unknown run '{}'", run_id)));
+            }
+            run_id.to_string()
+        }
+        // The latest run that modified a file, past any left empty by a run that stopped early
+        None => loop {
+            let run_id = known.pop().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no run to undo"))?;
+            if !read_manifest(&runs_dir.join(&run_id))?.is_empty() {
+                break run_id;
+            }
+        },
+    };
+    let run_dir = runs_dir.join(&run_id);
+
+    let entries = read_manifest(&run_dir)?;
+    if !force {
+        let mut changed = Vec::new();
+        for entry in &entries {
+            let current = match sha256_file(&entry.path) {
+                Ok(sum) => Some(sum),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            if current.as_deref() != Some(entry.new_sha256.as_str()) {
+                changed.push(entry.path.display().to_string());
+            }
+        }
+        if !changed.is_empty() {
+            return Err(io::Error::other(format!(
+                "refusing to undo run '{}': files changed since the run: {}",
+                run_id,
+                changed.join(", ")
+            )));
+        }
+    }
+
+    let mut outcome = UndoOutcome { run_id, ..Default::default() };
+    // Latest changes first, in case a file was written more than once
+    for entry in entries.iter().rev() {
+        match &entry.saved {
+            Some(saved) => {
+                let mut original = Vec::new();
+                File::open(run_dir.join(saved))?.read_to_end(&mut original)?;
+                write_file_atomic(&entry.path, &ReplacementConfig::new(), None, |w| w.write_all(&original))?;
+                outcome.restored.push(entry.path.clone());
+            }
+            None => {
+                match fs::remove_file(&entry.path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+                outcome.removed.push(entry.path.clone());
+            }
+        }
+    }
+
+    fs::remove_dir_all(&run_dir)?;
+    Ok(outcome)
+}
+
+/// Hex-encoded SHA-256 of a file's content
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Writer computing the SHA-256 of everything written through it
+pub struct HashingWriter<'a> {
+    inner: &'a mut dyn Write,
+    hasher: Sha256,
+}
+
+impl<'a> HashingWriter<'a> {
+    pub fn new(inner: &'a mut dyn Write) -> Self {
+        Self { inner, hasher: Sha256::new() }
+    }
+
+    /// Hex-encoded SHA-256 of the bytes written so far
+    pub fn finish(self) -> String {
+        to_hex(&self.hasher.finalize())
+    }
+}
+
+impl Write for HashingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn read_manifest(run_dir: &Path) -> io::Result<Vec<JournalEntry>> {
+    let file = match File::open(run_dir.join(MANIFEST_NAME)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        // A run interrupted mid-write may leave a truncated last line
+        if let Ok(entry) = serde_json::from_str(&line) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Run identifiers sort chronologically, so the last one is the latest run
+fn run_ids(runs_dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(runs_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut ids = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && let Some(name) = entry.file_name().to_str() {
+            ids.push(name.to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// UTC timestamp with millisecond precision, e.g. `20260117-093012.345`
+fn new_run_id() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time_of_day = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}.{:03}",
+        year, month, day,
+        time_of_day / 3600, time_of_day / 60 % 60, time_of_day % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_restores_files() {
+        let state = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        let existing = work.path().join("existing.txt");
+        let created = work.path().join("created.txt");
+        fs::write(&existing, "original").unwrap();
+
+        let journal = Journal::create_in(state.path()).unwrap();
+        let config = ReplacementConfig::new();
+        write_file_atomic(&existing, &config, Some(&journal), |w| w.write_all(b"modified")).unwrap();
+        write_file_atomic(&created, &config, Some(&journal), |w| w.write_all(b"new file")).unwrap();
+        let run_id = journal.run_id().to_string();
+        drop(config);
+        assert!(journal.finish().unwrap());
+
+        let runs = list_runs(state.path()).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].files, 2);
+
+        let outcome = undo_run(state.path(), None, false).unwrap();
+        assert_eq!(outcome.run_id, run_id);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "original");
+        assert!(!created.exists());
+        assert!(list_runs(state.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undo_refuses_changed_files() {
+        let state = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        let path = work.path().join("file.txt");
+        fs::write(&path, "original").unwrap();
+
+        let journal = Journal::create_in(state.path()).unwrap();
+        write_file_atomic(&path, &ReplacementConfig::new(), Some(&journal), |w| w.write_all(b"modified")).unwrap();
+        let run_id = journal.run_id().to_string();
+        fs::write(&path, "edited by hand").unwrap();
+
+        let err = undo_run(state.path(), Some(&run_id), false).unwrap_err();
+        assert!(err.to_string().contains("changed since the run"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "edited by hand");
+
+        undo_run(state.path(), Some(&run_id), true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+    }
+
+    #[test]
+    fn test_undo_rejects_unknown_runs() {
+        let state = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        let path = work.path().join("file.txt");
+        let journal = Journal::create_in(state.path()).unwrap();
+        write_file_atomic(&path, &ReplacementConfig::new(), Some(&journal), |w| w.write_all(b"new file")).unwrap();
+
+        for run_id in ["", ".", "..", "../..", "missing"] {
+            let err = undo_run(state.path(), Some(run_id), true).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound, "run id {run_id:?}");
+        }
+        assert!(state.path().join("runs").is_dir());
+        assert_eq!(list_runs(state.path()).unwrap().len(), 1);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_empty_runs_are_discarded() {
+        let state = tempfile::tempdir().unwrap();
+        assert!(!Journal::create_in(state.path()).unwrap().finish().unwrap());
+        assert!(list_runs(state.path()).unwrap().is_empty());
+        assert_eq!(undo_run(state.path(), None, false).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        // A run left empty by an early stop is passed over for the latest one that modified a file
+        let work = tempfile::tempdir().unwrap();
+        let path = work.path().join("file.txt");
+        fs::write(&path, "original").unwrap();
+        let journal = Journal::create_in(state.path()).unwrap();
+        write_file_atomic(&path, &ReplacementConfig::new(), Some(&journal), |w| w.write_all(b"modified")).unwrap();
+        let run_id = journal.run_id().to_string();
+        let _unfinished = Journal::create_in(state.path()).unwrap();
+        assert_eq!(list_runs(state.path()).unwrap().len(), 2);
+
+        assert_eq!(undo_run(state.path(), None, false).unwrap().run_id, run_id);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use serde_json::Value;
+
+/// Load `from → to` pairs from a mapping file, keeping the file's order
+///
+/// The format follows the extension:
+/// - `.json`: an object `{"from": "to", ...}`, or an array of `["from", "to"]` pairs
+/// - `.csv`: two comma separated columns, with the usual double quote escaping and no header row
+/// - anything else: tab separated values, one pair per line, taken literally
+pub fn load_pattern_map(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    let pairs = match extension.as_deref() {
+        Some("json") => read_json_map(BufReader::new(File::open(path)?)),
+        Some("csv") => read_delimited_map(File::open(path)?, b',', true),
+        _ => read_delimited_map(File::open(path)?, b'\t', false),
+    };
+    pairs.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn read_json_map(reader: impl io::Read) -> io::Result<Vec<(String, String)>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let value: Value = serde_json::from_reader(reader).map_err(|e| invalid(e.to_string()))?;
+
+    match value {
+        Value::Object(map) => map.into_iter()
+            .map(|(from, to)| match to {
+                Value::String(to) => Ok((from, to)),
+                _ => Err(invalid(format!("replacement for '{}' is not a string", from))),
+            })
+            .collect(),
+        Value::Array(items) => items.into_iter()
+            .enumerate()
+            .map(|(index, item)| match item {
+                Value::Array(pair) => match <[Value; 2]>::try_from(pair) {
+                    Ok([Value::String(from), Value::String(to)]) => Ok((from, to)),
+                    _ => Err(invalid(format!("entry #{} is not a pair of strings", index + 1))),
+                },
+                _ => Err(invalid(format!("entry #{} is not a pair of strings", index + 1))),
+            })
+            .collect(),
+        _ => Err(invalid("expected an object or an array of pairs".to_string())),
+    }
+}
+
+fn read_delimited_map(reader: impl io::Read, delimiter: u8, quoting: bool) -> io::Result<Vec<(String, String)>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quoting(quoting)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut pairs = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let line = record.position().map_or(0, |position| position.line());
+        match (record.get(0), record.get(1), record.len()) {
+            (Some(from), Some(to), 2) => pairs.push((from.to_string(), to.to_string())),
+            (_, _, fields) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected 2 fields, found {}", line, fields)
+                ));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load(name: &str, content: &str) -> io::Result<Vec<(String, String)>> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        load_pattern_map(&path)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn test_load_tsv() {
+        let map = load("keys.tsv", "old.key\tnew.key\r\n\"quoted\"\tkeeps, commas\n\nempty\t\n").unwrap();
+        assert_eq!(map, pairs(&[("old.key", "new.key"), ("\"quoted\"", "keeps, commas"), ("empty", "")]));
+
+        let err = load("keys.tsv", "a\tb\nmissing tab\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn test_load_csv() {
+        let map = load("keys.CSV", "a,b\n\"with, comma\",\"with \"\"quotes\"\"\"\n").unwrap();
+        assert_eq!(map, pairs(&[("a", "b"), ("with, comma", "with \"quotes\"")]));
+    }
+
+    #[test]
+    fn test_load_json_keeps_order() {
+        let map = load("keys.json", r#"{"zeta": "z", "alpha": "a"}"#).unwrap();
+        assert_eq!(map, pairs(&[("zeta", "z"), ("alpha", "a")]));
+
+        let map = load("keys.json", r#"[["x", "y"], ["x", "z"]]"#).unwrap();
+        assert_eq!(map, pairs(&[("x", "y"), ("x", "z")]));
+
+        assert!(load("keys.json", r#"{"a": 1}"#).is_err());
+        assert!(load("keys.json", r#"[["a", "b", "c"]]"#).is_err());
+    }
+}
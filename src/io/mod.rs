@@ -1,5 +1,20 @@
+pub mod atomic;
+pub mod backup;
 pub mod buffered;
+pub mod diff;
+pub mod encoding;
+pub mod journal;
+pub mod mapping;
+pub mod report;
+pub mod search;
 pub mod streaming;
+pub mod transcode;
+pub mod walk;
 
-pub use buffered::process_file_buffered;
-pub use streaming::process_file_streaming;
+pub use buffered::{process_file_buffered, process_files_parallel};
+pub use streaming::{process_file_streaming, process_stream};
+pub use walk::{WalkConfig, collect_files};
+pub use mapping::load_pattern_map;
+pub use journal::{Journal, list_runs, undo_run};
+pub use report::{Engine, FileReport, ReportFormat, STDIN_NAME, write_report};
+pub use search::{MatchLocation, SearchResult, locate_matches, search_file, search_files_parallel, search_reader};
@@ -0,0 +1,171 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use serde::{Serialize, Serializer};
+use crate::error::{Error, Warning};
+pub use crate::core::ReportFormat;
+
+/// Name standing for stdin, or for input given in memory, in reports, search results and warnings
+pub const STDIN_NAME: &str = "<stdin>";
+
+
+/// How the content of a file was processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Sequential,
+    Parallel,
+    Streaming,
+}
+
+/// What a run did to one file, or to stdin
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    /// Name of the encoding the content was decoded with; `None` if it was never decoded
+    pub encoding: Option<&'static str>,
+    pub bom: bool,
+    /// Name of the encoding the output was written in, when it differs from `encoding`
+    pub output_encoding: Option<&'static str>,
+    /// How sure the detection of `encoding` was, from 0 to 1; `None` if the encoding was given
+    pub confidence: Option<f32>,
+    pub engine: Engine,
+    pub bytes_in: u64,
+    /// Bytes written, or that would be written by a dry run; an unchanged file counts its input
+    pub bytes_out: u64,
+    pub replacements: usize,
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+    pub warnings: Vec<Warning>,
+    /// Why the file was left alone without being searched, e.g. `binary`
+    pub skipped: Option<&'static str>,
+    /// Why the file could not be processed; it was then left untouched
+    pub error: Option<String>,
+    /// The unified diff of a dry run, for the caller to print; `None` when nothing would change
+    #[serde(skip)]
+    pub diff: Option<String>,
+    /// Whether a dry run left out the diff, as it does for a streamed file
+    #[serde(skip)]
+    pub diff_skipped: bool,
+}
+
+impl FileReport {
+    pub fn new(path: impl Into<String>) -> Self {
+        FileReport {
+            path: path.into(),
+            encoding: None,
+            bom: false,
+            output_encoding: None,
+            confidence: None,
+            engine: Engine::Sequential,
+            bytes_in: 0,
+            bytes_out: 0,
+            replacements: 0,
+            elapsed: Duration::ZERO,
+            warnings: Vec::new(),
+            skipped: None,
+            error: None,
+            diff: None,
+            diff_skipped: false,
+        }
+    }
+
+    /// The report of a file that could not be processed, with the error but not its path again
+    pub fn failed(path: impl Into<String>, error: &Error) -> Self {
+        let mut report = FileReport::new(path);
+        report.error = Some(match error {
+            Error::Io { source, .. } => source.to_string(),
+            Error::Warning { warning, .. } => warning.to_string(),
+            error => error.to_string(),
+        });
+        report
+    }
+}
+
+fn serialize_millis<S: Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(elapsed.as_secs_f64() * 1000.0)
+}
+
+/// Write the records of a run in the given format
+pub fn write_report(writer: &mut impl Write, reports: &[FileReport], format: ReportFormat) -> io::Result<()> {
+    match format {
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *writer, reports).map_err(io::Error::other)?;
+            writer.write_all(b"\n")?;
+        }
+        ReportFormat::Ndjson => {
+            for report in reports {
+                serde_json::to_writer(&mut *writer, report).map_err(io::Error::other)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// A reader or writer counting the bytes going through it
+pub(crate) struct Counted<T> {
+    inner: T,
+    pub(crate) count: u64,
+}
+
+impl<T> Counted<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Counted { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_report() {
+        let mut report = FileReport::new("a.txt");
+        report.encoding = Some("UTF-8");
+        report.replacements = 2;
+        report.elapsed = Duration::from_micros(1500);
+        let error = Error::Io { path: Some("c.txt".into()), source: io::Error::from(io::ErrorKind::NotFound) };
+        let reports = [report, FileReport::new("b.bin"), FileReport::failed("c.txt", &error)];
+
+        let mut ndjson = Vec::new();
+        write_report(&mut ndjson, &reports, ReportFormat::Ndjson).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(ndjson).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["path"], "a.txt");
+        assert_eq!(lines[0]["engine"], "sequential");
+        assert_eq!(lines[0]["replacements"], 2);
+        assert_eq!(lines[0]["elapsed_ms"], 1.5);
+        assert_eq!(lines[1]["encoding"], serde_json::Value::Null);
+        assert_eq!(lines[1]["error"], serde_json::Value::Null);
+        assert_eq!(lines[2]["path"], "c.txt");
+        assert_eq!(lines[2]["error"], "entity not found");
+
+        let mut json = Vec::new();
+        write_report(&mut json, &reports, ReportFormat::Json).unwrap();
+        let array: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(array.as_array().unwrap().len(), 3);
+    }
+}
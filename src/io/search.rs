@@ -0,0 +1,178 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use encoding_rs::Encoding;
+use memchr::{memchr, memchr_iter, memrchr};
+use crate::core::{Match, Occurrences, display_escaped, escape_bytes, normalize_line_endings, ReplacementConfig, build_configured_regex, find_selected_matches};
+use super::encoding::{decode_content, looks_binary};
+use crate::error::{Error, Result, Warning, check_strict};
+
+/// A match found by a search, located by line and column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchLocation {
+    /// Line the match starts on, from 1
+    pub line: usize,
+    /// Column of the first matched character in its line, from 1, counted in characters
+    pub column: usize,
+    /// The line the match starts on, without its terminator
+    pub context: String,
+}
+
+/// The matches found in one input by a search
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub path: String,
+    pub matches: Vec<MatchLocation>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Find what a replacement with the same pattern and configuration would replace, without
+/// replacing anything
+///
+/// With `encoding` set to `None`, the encoding is detected, and a file that looks binary or whose
+/// encoding is detected with less than the minimum confidence gives no match.
+pub fn search_file(
+    path: &Path,
+    pattern: &str,
+    encoding: Option<&'static Encoding>,
+    config: &ReplacementConfig
+) -> Result<SearchResult> {
+    let buffer = fs::read(path)?;
+    let name = path.display().to_string();
+    if encoding.is_none() && looks_binary(&buffer) {
+        return Ok(SearchResult { path: name, matches: Vec::new(), warnings: Vec::new() });
+    }
+    let result = search_content(&buffer, name, pattern, encoding, config)?;
+    check_strict(config.strict, Some(path), &result.warnings)?;
+    Ok(result)
+}
+
+/// Search a stream, e.g. stdin, read to its end
+pub fn search_reader(
+    reader: &mut impl Read,
+    name: &str,
+    pattern: &str,
+    encoding: Option<&'static Encoding>,
+    config: &ReplacementConfig
+) -> Result<SearchResult> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    let result = search_content(&buffer, name.to_string(), pattern, encoding, config)?;
+    check_strict(config.strict, None, &result.warnings)?;
+    Ok(result)
+}
+
+/// Search several files in parallel, returning their results in input order
+pub fn search_files_parallel(
+    file_paths: &[&Path],
+    pattern: &str,
+    encoding: Option<&'static Encoding>,
+    config: &ReplacementConfig
+) -> Result<Vec<SearchResult>> {
+    use rayon::prelude::*;
+
+    let search = |&path: &&Path| {
+        search_file(path, pattern, encoding, config).map_err(|e| e.with_path(path))
+    };
+    if config.occurrences.as_ref().is_some_and(Occurrences::is_global) {
+        // Occurrences numbered across files are numbered in the order of the files
+        file_paths.iter().map(search).collect()
+    } else {
+        file_paths.par_iter().map(search).collect()
+    }
+}
+
+fn search_content(
+    buffer: &[u8],
+    path: String,
+    pattern: &str,
+    encoding: Option<&'static Encoding>,
+    config: &ReplacementConfig
+) -> Result<SearchResult> {
+    let config = config.for_file();
+    let config = config.as_ref();
+    let regex = build_configured_regex(pattern, config).map_err(Error::invalid_input)?;
+
+    let mut warnings = Vec::new();
+    let content = if config.byte_mode {
+        escape_bytes(buffer)
+    } else {
+        let (content, _, _, confidence) = decode_content(buffer, encoding, &mut warnings);
+        // Like a binary file, a file whose encoding is too uncertain gives no match
+        if config.rejects_confidence(confidence) {
+            return Ok(SearchResult { path, matches: Vec::new(), warnings: Vec::new() });
+        }
+        content
+    };
+    // Lines and columns are the same once CRLF is LF; only the terminators go
+    let content = if config.eol_agnostic { normalize_line_endings(&content) } else { Cow::Borrowed(content.as_ref()) };
+    let matches = find_selected_matches(&content, pattern, regex.as_ref(), config);
+    let mut matches = locate_matches(&content, &matches);
+    if config.byte_mode {
+        for location in &mut matches {
+            location.context = display_escaped(&location.context).into_owned();
+        }
+    }
+    Ok(SearchResult { path, matches, warnings })
+}
+
+/// Line, column and line text of each match, given in content order
+pub fn locate_matches(content: &str, matches: &[Match]) -> Vec<MatchLocation> {
+    let bytes = content.as_bytes();
+    let mut line = 1;
+    let mut counted = 0;
+
+    matches.iter()
+        .map(|m| {
+            line += memchr_iter(b'\n', &bytes[counted..m.start]).count();
+            counted = m.start;
+            let line_start = memrchr(b'\n', &bytes[..m.start]).map_or(0, |newline| newline + 1);
+            let line_end = memchr(b'\n', &bytes[m.start..]).map_or(bytes.len(), |newline| m.start + newline);
+            let text = &content[line_start..line_end];
+            MatchLocation {
+                line,
+                column: content[line_start..m.start].chars().count() + 1,
+                context: text.strip_suffix('\r').unwrap_or(text).to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::find_matches;
+
+    #[test]
+    fn test_locate_matches() {
+        let content = "first\r\nsecond: é id id\n\nid";
+        let locations = locate_matches(content, &find_matches(content, "id", false));
+        let positions: Vec<(usize, usize)> = locations.iter().map(|l| (l.line, l.column)).collect();
+        assert_eq!(positions, [(2, 11), (2, 14), (4, 1)]);
+        assert_eq!(locations[0].context, "second: é id id");
+        assert_eq!(locations[2].context, "id");
+    }
+
+    #[test]
+    fn test_search_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("a.txt");
+        let latin1 = dir.path().join("b.txt");
+        let binary = dir.path().join("c.bin");
+        fs::write(&text, "key = 1\nother\nkey = 2\n").unwrap();
+        fs::write(&latin1, b"caf\xE9 key").unwrap();
+        fs::write(&binary, b"key\0key").unwrap();
+
+        let config = ReplacementConfig::new().with_occurrences(Some(Occurrences::nth(2)));
+        let paths = [text.as_path(), latin1.as_path(), binary.as_path()];
+        let results = search_files_parallel(&paths, "key", None, &config).unwrap();
+        let counts: Vec<usize> = results.iter().map(|r| r.matches.len()).collect();
+        assert_eq!(counts, [1, 0, 0]);
+        assert_eq!(results[0].matches[0], MatchLocation { line: 3, column: 1, context: "key = 2".to_string() });
+        assert_eq!(results[1].warnings.len(), 1);
+
+        // Searching leaves the files as they were
+        assert_eq!(fs::read_to_string(&text).unwrap(), "key = 1\nother\nkey = 2\n");
+    }
+}
@@ -0,0 +1,318 @@
+use std::io::{self, Read, Write};
+use encoding_rs::{CoderResult, Decoder, Encoder, Encoding};
+use super::encoding::encode_text;
+use crate::core::bytes::{escape_bytes, unescape_bytes};
+use crate::core::case::complete_utf8_len;
+
+/// Smallest internal buffer, so that a decoded or encoded character always fits
+const MIN_BUFFER_LEN: usize = 64;
+
+/// A reader decoding a byte stream in some encoding to UTF-8 as it is read
+///
+/// The decoder keeps its state between reads, so a multibyte sequence or a UTF-16 code unit split
+/// across two reads is decoded whole. A BOM is decoded as U+FEFF, which `EncodingWriter` writes
+/// back as it was. Invalid sequences are decoded as U+FFFD.
+pub struct DecodingReader<R> {
+    inner: R,
+    decoder: Decoder,
+    raw: Vec<u8>,
+    raw_start: usize,
+    raw_end: usize,
+    decoded: Vec<u8>,
+    decoded_start: usize,
+    decoded_end: usize,
+    eof: bool,
+    finished: bool,
+    had_errors: bool,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R, encoding: &'static Encoding, buffer_size: usize) -> Self {
+        let buffer_size = buffer_size.max(MIN_BUFFER_LEN);
+        DecodingReader {
+            inner,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            raw: vec![0; buffer_size],
+            raw_start: 0,
+            raw_end: 0,
+            decoded: vec![0; buffer_size],
+            decoded_start: 0,
+            decoded_end: 0,
+            eof: false,
+            finished: false,
+            had_errors: false,
+        }
+    }
+
+    /// Whether some bytes read so far were invalid in the encoding
+    pub fn had_errors(&self) -> bool {
+        self.had_errors
+    }
+
+    fn fill_decoded(&mut self) -> io::Result<()> {
+        if self.raw_start == self.raw_end && !self.eof {
+            let n = self.inner.read(&mut self.raw)?;
+            self.raw_start = 0;
+            self.raw_end = n;
+            self.eof = n == 0;
+        }
+        let (result, read, written, had_errors) =
+            self.decoder.decode_to_utf8(&self.raw[self.raw_start..self.raw_end], &mut self.decoded, self.eof);
+        self.raw_start += read;
+        self.decoded_start = 0;
+        self.decoded_end = written;
+        self.had_errors |= had_errors;
+        // The last call flushes a sequence left incomplete at the end of the input
+        self.finished = self.eof && result == CoderResult::InputEmpty;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded_start == self.decoded_end {
+            if self.finished || buf.is_empty() {
+                return Ok(0);
+            }
+            self.fill_decoded()?;
+        }
+        let n = buf.len().min(self.decoded_end - self.decoded_start);
+        buf[..n].copy_from_slice(&self.decoded[self.decoded_start..self.decoded_start + n]);
+        self.decoded_start += n;
+        Ok(n)
+    }
+}
+
+/// A writer encoding the UTF-8 written to it into some encoding
+///
+/// A character split across two writes is encoded whole, and stateful encodings such as
+/// ISO-2022-JP keep their state between writes. Characters the encoding cannot represent are
+/// written as HTML numeric character references. Call `finish` once everything is written.
+pub struct EncodingWriter<W> {
+    inner: W,
+    encoding: &'static Encoding,
+    /// `None` for UTF-16, which encoding_rs only encodes as UTF-8
+    encoder: Option<Encoder>,
+    /// The start of a character whose remaining bytes are not written yet
+    partial: Vec<u8>,
+    encoded: Vec<u8>,
+    had_errors: bool,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    pub fn new(inner: W, encoding: &'static Encoding) -> Self {
+        let utf16 = encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE;
+        EncodingWriter {
+            inner,
+            encoding,
+            encoder: (!utf16).then(|| encoding.new_encoder()),
+            partial: Vec::new(),
+            encoded: vec![0; 8 * 1024],
+            had_errors: false,
+        }
+    }
+
+    /// Whether some characters written so far could not be represented in the encoding
+    pub fn had_errors(&self) -> bool {
+        self.had_errors
+    }
+
+    /// Write out what the encoder holds back and the end of the input, e.g. the final escape
+    /// sequence of ISO-2022-JP, then flush
+    pub fn finish(&mut self) -> io::Result<()> {
+        // Bytes left over are not UTF-8; they are encoded as U+FFFD
+        let rest = String::from_utf8_lossy(&std::mem::take(&mut self.partial)).into_owned();
+        self.encode(&rest, true)?;
+        self.inner.flush()
+    }
+
+    fn encode(&mut self, text: &str, last: bool) -> io::Result<()> {
+        let Some(encoder) = &mut self.encoder else {
+            let (bytes, had_errors) = encode_text(self.encoding, text);
+            self.had_errors |= had_errors;
+            return self.inner.write_all(&bytes);
+        };
+        let mut text = text;
+        loop {
+            let (result, read, written, had_errors) = encoder.encode_from_utf8(text, &mut self.encoded, last);
+            self.had_errors |= had_errors;
+            self.inner.write_all(&self.encoded[..written])?;
+            text = &text[read..];
+            if result == CoderResult::InputEmpty {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let partial = std::mem::take(&mut self.partial);
+        let valid = match std::str::from_utf8(&partial) {
+            Ok(text) => text.len(),
+            // An incomplete character at the end waits for the next write
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                self.encode(&String::from_utf8_lossy(&partial), false)?;
+                return Ok(buf.len());
+            }
+        };
+        // Valid UTF-8 up to `valid`, as just checked
+        self.encode(std::str::from_utf8(&partial[..valid]).unwrap(), false)?;
+        self.partial = partial[valid..].to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader turning mostly UTF-8 content into text as it is read, like `escape_bytes`, for the
+/// byte mode
+///
+/// A character cut by a read waits for the next one, so that it is not taken for invalid bytes.
+pub struct EscapingReader<R> {
+    inner: R,
+    raw: Vec<u8>,
+    /// Bytes read but not escaped yet
+    carry: Vec<u8>,
+    escaped: Vec<u8>,
+    escaped_start: usize,
+    eof: bool,
+}
+
+impl<R: Read> EscapingReader<R> {
+    pub fn new(inner: R, buffer_size: usize) -> Self {
+        EscapingReader {
+            inner,
+            raw: vec![0; buffer_size.max(MIN_BUFFER_LEN)],
+            carry: Vec::new(),
+            escaped: Vec::new(),
+            escaped_start: 0,
+            eof: false,
+        }
+    }
+
+    fn fill_escaped(&mut self) -> io::Result<()> {
+        let n = self.inner.read(&mut self.raw)?;
+        self.eof = n == 0;
+        self.carry.extend_from_slice(&self.raw[..n]);
+        let complete = if self.eof { self.carry.len() } else { complete_utf8_len(&self.carry) };
+        self.escaped = escape_bytes(&self.carry[..complete]).into_owned().into_bytes();
+        self.escaped_start = 0;
+        self.carry.drain(..complete);
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for EscapingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.escaped_start == self.escaped.len() {
+            if self.eof || buf.is_empty() {
+                return Ok(0);
+            }
+            self.fill_escaped()?;
+        }
+        let n = buf.len().min(self.escaped.len() - self.escaped_start);
+        buf[..n].copy_from_slice(&self.escaped[self.escaped_start..self.escaped_start + n]);
+        self.escaped_start += n;
+        Ok(n)
+    }
+}
+
+/// A writer turning the text of an `EscapingReader` back into the original bytes
+///
+/// Call `finish` once everything is written.
+pub struct UnescapingWriter<W> {
+    inner: W,
+    /// The start of a character whose remaining bytes are not written yet
+    partial: Vec<u8>,
+}
+
+impl<W: Write> UnescapingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        UnescapingWriter { inner, partial: Vec::new() }
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.inner.write_all(&std::mem::take(&mut self.partial))?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for UnescapingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let complete = complete_utf8_len(&self.partial);
+        match std::str::from_utf8(&self.partial[..complete]) {
+            Ok(text) => self.inner.write_all(&unescape_bytes(text))?,
+            // Not escaped text: written as it is
+            Err(_) => self.inner.write_all(&self.partial[..complete])?,
+        }
+        self.partial.drain(..complete);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader handing out at most `step` bytes per read
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(self.1).min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_round_trip_split_sequences() {
+        let text = "\u{FEFF}日本語のテキスト 😀 é\n".repeat(20);
+        for encoding in [encoding_rs::UTF_16LE, encoding_rs::UTF_16BE, encoding_rs::SHIFT_JIS, encoding_rs::GBK, encoding_rs::ISO_2022_JP] {
+            // Characters missing from the encoding become character references, which round-trip too
+            let (raw, _) = encode_text(encoding, &text);
+            for step in [1, 3, 7] {
+                let mut decoded = String::new();
+                let mut reader = DecodingReader::new(Trickle(&raw, step), encoding, 1);
+                reader.read_to_string(&mut decoded).unwrap();
+                assert!(!reader.had_errors(), "{} {}", encoding.name(), step);
+
+                let mut writer = EncodingWriter::new(Vec::new(), encoding);
+                for piece in decoded.as_bytes().chunks(step) {
+                    writer.write_all(piece).unwrap();
+                }
+                writer.finish().unwrap();
+                assert_eq!(writer.inner, raw.as_ref(), "{} {}", encoding.name(), step);
+            }
+        }
+    }
+
+    #[test]
+    fn test_escaping_round_trip() {
+        let content = b"caf\xE9 na\xC3\xAFve \xF0\x9F\x98\x80 \xFF\n".repeat(20);
+        for step in [1, 2, 5] {
+            let mut text = String::new();
+            EscapingReader::new(Trickle(&content, step), 1).read_to_string(&mut text).unwrap();
+            assert_eq!(text.matches("naïve").count(), 20);
+            assert_eq!(text.matches('😀').count(), 20);
+
+            let mut writer = UnescapingWriter::new(Vec::new());
+            for piece in text.as_bytes().chunks(step) {
+                writer.write_all(piece).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_eq!(writer.inner, content);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+
+/// Options controlling how directories are expanded into files to process
+#[derive(Debug, Clone)]
+pub struct WalkConfig {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub max_depth: Option<usize>,
+    pub include_hidden: bool,
+    pub respect_ignore_files: bool,
+}
+
+impl Default for WalkConfig {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            include_hidden: false,
+            respect_ignore_files: true,
+        }
+    }
+}
+
+impl WalkConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_include(mut self, globs: Vec<String>) -> Self {
+        self.include = globs;
+        self
+    }
+
+    pub fn with_exclude(mut self, globs: Vec<String>) -> Self {
+        self.exclude = globs;
+        self
+    }
+
+    pub fn with_max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn with_hidden(mut self, enabled: bool) -> Self {
+        self.include_hidden = enabled;
+        self
+    }
+
+    pub fn with_ignore_files(mut self, enabled: bool) -> Self {
+        self.respect_ignore_files = enabled;
+        self
+    }
+}
+
+/// Expand a list of files and directories into the sorted list of files to process
+///
+/// Files given explicitly are always kept. Files found inside directories are filtered by
+/// `include`/`exclude` globs matched against their path relative to the directory given and,
+/// unless disabled, by `.gitignore`, `.ignore`, `.git/info/exclude` and the global git excludes
+/// the same way ripgrep does. `.git` directories are never descended into.
+pub fn collect_files(paths: &[PathBuf], config: &WalkConfig) -> io::Result<Vec<PathBuf>> {
+    let include = build_globset(&config.include)?;
+    let exclude = build_globset(&config.exclude)?;
+    let mut files = Vec::new();
+
+    for root in paths {
+        if !root.is_dir() {
+            // Surface missing paths as a NotFound error rather than skipping them
+            std::fs::metadata(root)?;
+            files.push(root.clone());
+            continue;
+        }
+
+        let mut walk = WalkBuilder::new(root);
+        walk.standard_filters(false)
+            .hidden(!config.include_hidden)
+            .max_depth(config.max_depth)
+            .parents(config.respect_ignore_files)
+            .ignore(config.respect_ignore_files)
+            .git_ignore(config.respect_ignore_files)
+            .git_global(config.respect_ignore_files)
+            .git_exclude(config.respect_ignore_files);
+
+        // Prune excluded directories instead of walking through them
+        let prune_root = root.clone();
+        let prune = exclude.clone();
+        walk.filter_entry(move |entry| {
+            if entry.depth() == 0 || !entry.file_type().is_some_and(|t| t.is_dir()) {
+                return true;
+            }
+            entry.file_name() != ".git" && !prune.is_match(relative_path(&prune_root, entry.path()))
+        });
+
+        let mut found = Vec::new();
+        for entry in walk.build() {
+            let entry = entry.map_err(walk_error)?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+
+            let relative = relative_path(root, entry.path());
+            if !config.include.is_empty() && !include.is_match(relative) {
+                continue;
+            }
+            if exclude.is_match(relative) {
+                continue;
+            }
+            found.push(entry.into_path());
+        }
+
+        found.sort();
+        files.extend(found);
+    }
+
+    // Overlapping arguments must not hand the same file to two workers
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files)
+}
+
+fn build_globset(globs: &[String]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = Glob::new(glob).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn relative_path<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+fn walk_error(err: ignore::Error) -> io::Error {
+    match err.into_io_error() {
+        Some(err) => err,
+        None => io::Error::other("failed to walk directory"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn create_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["a.rs", "b.txt", "src/c.rs", "src/deep/d.rs", "target/debug/e.rs", ".hidden/f.rs", ".g.rs"] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "content").unwrap();
+        }
+        dir
+    }
+
+    fn names(dir: &Path, files: &[PathBuf]) -> Vec<String> {
+        files.iter()
+            .map(|f| f.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_default() {
+        let dir = create_tree();
+        let files = collect_files(&[dir.path().to_path_buf()], &WalkConfig::new()).unwrap();
+        assert_eq!(names(dir.path(), &files), ["a.rs", "b.txt", "src/c.rs", "src/deep/d.rs", "target/debug/e.rs"]);
+    }
+
+    #[test]
+    fn test_walk_include_exclude() {
+        let dir = create_tree();
+        let config = WalkConfig::new()
+            .with_include(vec!["*.rs".to_string()])
+            .with_exclude(vec!["target/**".to_string(), "src/deep".to_string()]);
+        let files = collect_files(&[dir.path().to_path_buf()], &config).unwrap();
+        assert_eq!(names(dir.path(), &files), ["a.rs", "src/c.rs"]);
+    }
+
+    #[test]
+    fn test_walk_depth_and_hidden() {
+        let dir = create_tree();
+        let config = WalkConfig::new().with_max_depth(Some(1)).with_hidden(true);
+        let files = collect_files(&[dir.path().to_path_buf()], &config).unwrap();
+        assert_eq!(names(dir.path(), &files), [".g.rs", "a.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn test_walk_respects_ignore_files() {
+        let dir = create_tree();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/config"), "content").unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.path().join("src/.ignore"), "deep\n").unwrap();
+
+        let files = collect_files(&[dir.path().to_path_buf()], &WalkConfig::new()).unwrap();
+        assert_eq!(names(dir.path(), &files), ["a.rs", "b.txt", "src/c.rs"]);
+
+        let config = WalkConfig::new().with_ignore_files(false).with_hidden(true);
+        let files = collect_files(&[dir.path().to_path_buf()], &config).unwrap();
+        assert_eq!(
+            names(dir.path(), &files),
+            [".g.rs", ".gitignore", ".hidden/f.rs", "a.rs", "b.txt", "src/.ignore", "src/c.rs", "src/deep/d.rs", "target/debug/e.rs"]
+        );
+    }
+
+    #[test]
+    fn test_explicit_files_bypass_filters() {
+        let dir = create_tree();
+        let hidden = dir.path().join(".g.rs");
+        let config = WalkConfig::new().with_include(vec!["*.txt".to_string()]);
+        let files = collect_files(std::slice::from_ref(&hidden), &config).unwrap();
+        assert_eq!(files, [hidden]);
+
+        let missing = collect_files(&[dir.path().join("missing.rs")], &config);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
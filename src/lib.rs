@@ -1,26 +1,98 @@
+pub mod api;
 pub mod core;
+pub mod error;
 pub mod io;
 
 pub use core::{
+    BackupMode,
+    BomMode,
+    EolMode,
+    CasePreserver,
+    CaseSensitivity,
+    LineScope,
+    Match,
+    Occurrences,
     ReplacementConfig, 
+    ReportFormat,
+    WordBoundary,
     perform_replacement, 
     perform_replacement_parallel, 
     perform_replacement_utf8_optimized,
     perform_multi_pattern_replacement,
-    perform_streaming_replacement
+    perform_streaming_replacement,
+    build_regex,
+    build_regex_with_case,
+    build_configured_regex,
+    perform_configured_replacement,
+    perform_streaming_configured_replacement,
+    find_configured_matches,
+    find_selected_matches,
+    find_regex_matches_parallel,
+    perform_replacement_ignore_case,
+    perform_replacement_parallel_ignore_case,
+    perform_streaming_replacement_ignore_case,
+    find_word_matches,
+    perform_word_replacement,
+    perform_word_replacement_parallel,
+    perform_streaming_word_replacement,
+    perform_regex_replacement,
+    perform_regex_replacement_parallel,
+    perform_streaming_regex_replacement,
+    MatchKind,
+    PatternSet,
+    build_pattern_set,
+    build_case_preserving_pattern_set,
+    find_matches,
+    find_pattern_set_matches,
+    find_pattern_set_matches_parallel,
+    find_matches_parallel,
+    replace_matches,
+    perform_pattern_set_replacement,
+    perform_pattern_set_replacement_parallel,
+    perform_streaming_pattern_set_replacement,
+    escape_bytes,
+    unescape_bytes,
+    perform_configured_byte_replacement
 };
+pub use api::{ReplaceOutcome, Replacer, ReplacerBuilder};
+pub use error::{Error, Result, Warning};
 pub use io::{
+    Engine,
+    FileReport,
+    Journal,
+    MatchLocation,
+    STDIN_NAME,
+    SearchResult,
+    WalkConfig,
+    collect_files,
+    load_pattern_map,
     process_file_buffered,
-    process_file_streaming
+    process_files_parallel,
+    process_file_streaming,
+    process_stream,
+    list_runs,
+    locate_matches,
+    search_file,
+    search_files_parallel,
+    search_reader,
+    undo_run,
+    write_report
 };
 
-use std::fs;
 use std::io::{self as stdio, Read, Write};
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Instant;
-use encoding_rs;
 use indicatif::{ProgressBar, ProgressStyle};
 
 /// Main high-level replacement function with automatic optimization
+///
+/// A command-line front end to [`Replacer::replace_path`]: it draws a spinner and prints the
+/// diff, the report or the statistics the configuration asks for. Returns the report of the
+/// file, whose warnings are left for the caller to show; in strict mode the first warning fails
+/// the call before the file is written. The written file is recorded in `journal` if one is given.
+#[allow(clippy::too_many_arguments)]
 pub fn replace_in_file(
     file_path: &str,
     pattern: &str,
@@ -28,34 +100,18 @@ pub fn replace_in_file(
     output_file: Option<&str>,
     encoding: Option<&str>,
     show_stats: bool,
-    config: Option<ReplacementConfig>
-) -> stdio::Result<()> {
-    let args = Args {
-        file: file_path.to_string(),
-        pattern: pattern.to_string(),
-        replacement: replacement.to_string(),
-        stat: show_stats,
-        output: output_file.map(|s| s.to_string()),
-        encoding: encoding.map(|s| s.to_string()),
-    };
-    
-    run_replacement(args, config.unwrap_or_default())
-}
-
-#[derive(Debug)]
-struct Args {
-    file: String,
-    pattern: String,
-    replacement: String,
-    stat: bool,
-    output: Option<String>,
-    encoding: Option<String>,
-}
-
-fn run_replacement(args: Args, config: ReplacementConfig) -> stdio::Result<()> {
+    config: Option<ReplacementConfig>,
+    journal: Option<Arc<Journal>>
+) -> Result<FileReport> {
     let start_time = Instant::now();
+    let replacer = build_replacer(pattern, replacement, encoding, config, journal)
+        .map_err(|e| e.with_path(Path::new(file_path)))?;
+    let config = replacer.config();
 
     let pb = ProgressBar::new_spinner();
+    if config.report.is_some() {
+        pb.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+    }
     pb.enable_steady_tick(std::time::Duration::from_millis(120));
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -71,91 +127,166 @@ fn run_replacement(args: Args, config: ReplacementConfig) -> stdio::Result<()> {
             .template("{spinner:.blue} {msg}")
             .unwrap(),
     );
-    pb.set_message("Reading file...");
-
-    let mut file = fs::File::open(&args.file)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    pb.set_message("Replacing content...");
 
-    pb.set_message("Decoding file...");
+    let outcome = match output_file {
+        Some(output_file) => replacer.replace_path_to(file_path, output_file),
+        None => replacer.replace_path(file_path),
+    };
+    pb.finish_and_clear();
+    let outcome = outcome?;
+    if let Some(diff) = &outcome.diff {
+        stdio::stdout().lock().write_all(diff.as_bytes())?;
+    }
+    let report = outcome.report;
+    let occurrences = report.replacements;
+    let elapsed_time = start_time.elapsed();
 
-    let (encoding, had_bom) = if let Some(enc_label) = &args.encoding {
-        (encoding_rs::Encoding::for_label(enc_label.as_bytes()).unwrap_or_else(|| {
-            eprintln!("Warning: Unknown encoding label '{}'. Defaulting to UTF-8.", enc_label);
-            encoding_rs::UTF_8
-        }), false)
-    } else {
-        if let Some((enc, _confidence)) = encoding_rs::Encoding::for_bom(&buffer) {
-            (enc, true)
+    if let Some(format) = config.report {
+        // A dry run prints its diff on stdout
+        if config.dry_run {
+            write_report(&mut stdio::stderr().lock(), std::slice::from_ref(&report), format)?;
         } else {
-            let (_cow, _, utf8_had_errors) = encoding_rs::UTF_8.decode(&buffer);
-            if !utf8_had_errors {
-                (encoding_rs::UTF_8, false)
-            } else {
-                eprintln!("Warning: Input file is not valid UTF-8. Attempting to decode as Windows-1252.");
-                (encoding_rs::WINDOWS_1252, false)
-            }
+            write_report(&mut stdio::stdout().lock(), std::slice::from_ref(&report), format)?;
         }
-    };
-
-    let (cow, _, had_errors) = encoding.decode(&buffer);
+        return Ok(report);
+    }
 
-    if had_errors && !had_bom {
-        eprintln!("Warning: Some characters could not be decoded from the detected/specified encoding. They might be replaced with U+FFFD (replacement character).");
+    if show_stats {
+        // A set built for a single pattern, e.g. to preserve case, is reported like that pattern
+        match (config.pattern_set.as_ref().filter(|_| pattern.is_empty()), config.dry_run) {
+            _ if report.skipped == Some("ambiguous encoding") => println!("Skipped '{}': its encoding could not be detected with enough confidence.", file_path),
+            (Some(set), true) => println!("Dry run: found {} occurrences of {} patterns in '{}'; no file was written.", occurrences, set.len(), file_path),
+            (Some(set), false) => println!("Successfully replaced all occurrences of {} patterns in '{}'.", set.len(), file_path),
+            (None, true) => println!("Dry run: found {} occurrences of '{}' in '{}'; no file was written.", occurrences, pattern, file_path),
+            (None, false) => println!("Successfully replaced all occurrences of '{}' with '{}' in '{}'.", pattern, replacement, file_path),
+        }
+        println!("----- Statistics -----");
+        println!("Replacements made: {}", occurrences);
+        match (report.encoding, report.confidence) {
+            (Some(encoding), Some(confidence)) => println!("Encoding: {} (detected, confidence {:.2})", encoding, confidence),
+            (Some(encoding), None) => println!("Encoding: {}", encoding),
+            (None, _) => {}
+        }
+        println!("Time taken: {:.2?} ", elapsed_time);
+        println!("Processing method: {}", match report.engine {
+            Engine::Parallel => "Parallel",
+            Engine::Sequential => "Optimized Sequential",
+            Engine::Streaming => "Streaming",
+        });
+        println!("----------------------");
     }
 
-    let contents = cow.into_owned();
+    Ok(report)
+}
 
-    pb.set_message("Replacing content...");
-    
-    // Use optimized processing
-    let (replaced_contents, occurrences) = if contents.len() > config.parallel_threshold {
-        pb.set_message("Processing with parallel algorithm...");
-        perform_replacement_parallel(&contents, &args.pattern, &args.replacement, config.parallel_threshold / 2)
-    } else {
-        pb.set_message("Processing with optimized algorithm...");
-        perform_replacement_utf8_optimized(&contents, &args.pattern, &args.replacement, config.enable_caching)
-    };
+/// Replace in standard input, writing the result to standard output (or to `output_file`)
+///
+/// The input is streamed, so this works as a filter in a pipeline. No spinner is drawn and
+/// statistics go to stderr, keeping stdout for the data, as does a report unless the data goes
+/// to `output_file`. A dry run has to read the whole input to print its diff. Warnings are returned
+/// in the report, as with [`replace_in_file`], and so is `journal`.
+pub fn replace_stdin(
+    pattern: &str,
+    replacement: &str,
+    output_file: Option<&str>,
+    encoding: Option<&str>,
+    show_stats: bool,
+    config: Option<ReplacementConfig>,
+    journal: Option<Arc<Journal>>
+) -> Result<FileReport> {
+    let replacer = build_replacer(pattern, replacement, encoding, config, None)?;
+    let config = replacer.config();
+    let output_file = output_file.filter(|path| *path != "-");
+    let mut stdin = stdio::stdin().lock();
 
-    pb.set_message("Writing to file...");
-    let output_path = if let Some(out_file) = &args.output {
-        out_file
+    let report = if config.dry_run {
+        let mut buffer = Vec::new();
+        stdin.read_to_end(&mut buffer)?;
+        let mut warnings = replacer.warnings().to_vec();
+        let (contents, encoding, had_bom, _) = if config.byte_mode {
+            (core::escape_bytes(&buffer), encoding_rs::UTF_8, false, None)
+        } else {
+            let encoding = replacer.encoding().unwrap_or(encoding_rs::UTF_8);
+            io::encoding::decode_content(&buffer, Some(encoding), &mut warnings)
+        };
+
+        let outcome = replacer.replace_str(&contents)?;
+        let (output_encoding, bom) = io::encoding::output_format(encoding, had_bom, config);
+        let (output, had_encoding_errors) = if config.byte_mode {
+            (core::unescape_bytes(&outcome.output), false)
+        } else {
+            io::encoding::encode_text(output_encoding, &outcome.output)
+        };
+        if had_encoding_errors {
+            warnings.push(Warning::UnencodableCharacters { encoding: output_encoding.name() });
+        }
+        error::check_strict(config.strict, None, &warnings)?;
+        let display = |text| if config.byte_mode { core::display_escaped(text) } else { Cow::Borrowed(text) };
+        let diff = io::diff::unified_diff(
+            &display(&contents),
+            &display(&outcome.output),
+            STDIN_NAME,
+            output_file.unwrap_or("<stdout>"),
+            config.diff_context,
+            config.diff_color
+        );
+        stdio::stdout().lock().write_all(diff.as_bytes())?;
+        FileReport {
+            encoding: Some(encoding.name()),
+            bom: had_bom,
+            output_encoding: (output_encoding != encoding).then(|| output_encoding.name()),
+            bytes_in: buffer.len() as u64,
+            bytes_out: (bom.len() + output.len()) as u64,
+            warnings,
+            ..outcome.report
+        }
+    } else if let Some(output_file) = output_file {
+        let mut outcome = None;
+        io::atomic::write_file_atomic(Path::new(output_file), config, journal.as_deref(), |writer| {
+            outcome = Some(replacer.replace_reader_to_writer(&mut stdin, writer)?);
+            Ok(())
+        })?;
+        outcome.expect("the output is written once it succeeds").report
     } else {
-        &args.file
+        let mut stdout = stdio::BufWriter::with_capacity(config.buffer_size, stdio::stdout().lock());
+        let outcome = replacer.replace_reader_to_writer(&mut stdin, &mut stdout)?;
+        stdout.flush()?;
+        outcome.report
     };
 
-    let (encoded_output, _, had_encoding_errors) = encoding.encode(&replaced_contents);
-    if had_encoding_errors {
-        eprintln!("Warning: Some characters in the replacement string could not be represented in the target encoding ('{}'). They may have been replaced by fallback characters.", encoding.name());
-    }
-
-    let mut file = fs::File::create(output_path)?;
-
-    if had_bom {
-        match encoding.name() {
-            "UTF-8" => file.write_all(&[0xEF, 0xBB, 0xBF])?,
-            "UTF-16LE" => file.write_all(&[0xFF, 0xFE])?,
-            "UTF-16BE" => file.write_all(&[0xFE, 0xFF])?,
-            _ => {}
+    if let Some(format) = config.report {
+        // Stdout carries the data or the diff unless the data goes to a file
+        if output_file.is_some() && !config.dry_run {
+            write_report(&mut stdio::stdout().lock(), std::slice::from_ref(&report), format)?;
+        } else {
+            write_report(&mut stdio::stderr().lock(), std::slice::from_ref(&report), format)?;
         }
+    } else if show_stats {
+        eprintln!("----- Statistics -----");
+        eprintln!("Replacements made: {}", report.replacements);
+        eprintln!("Time taken: {:.2?} ", report.elapsed);
+        eprintln!("Processing method: Streaming");
+        eprintln!("----------------------");
     }
 
-    file.write_all(&encoded_output)?;
-
-    pb.finish_and_clear();
-
-    let elapsed_time = start_time.elapsed();
+    Ok(report)
+}
 
-    if args.stat {
-        println!("Successfully replaced all occurrences of '{}' with '{}' in '{}'.", args.pattern, args.replacement, args.file);
-        println!("----- Statistics -----");
-        println!("Replacements made: {}", occurrences);
-        println!("Time taken: {:.2?} ", elapsed_time);
-        println!("Processing method: {}", 
-            if contents.len() > config.parallel_threshold { "Parallel" } 
-            else { "Optimized Sequential" });
-        println!("----------------------");
+fn build_replacer(
+    pattern: &str,
+    replacement: &str,
+    encoding: Option<&str>,
+    config: Option<ReplacementConfig>,
+    journal: Option<Arc<Journal>>
+) -> Result<Replacer> {
+    let mut builder = Replacer::builder()
+        .pattern(pattern)
+        .replacement(replacement)
+        .config(config.unwrap_or_default())
+        .journal(journal);
+    if let Some(label) = encoding {
+        builder = builder.encoding(label);
     }
-
-    Ok(())
+    builder.build()
 }
@@ -1,25 +1,116 @@
-use clap::Parser;
-use replacer::{replace_in_file, ReplacementConfig};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
+use replacer::{collect_files, list_runs, process_files_parallel, replace_in_file, replace_stdin, undo_run, write_report};
+use replacer::{search_files_parallel, search_reader};
+use replacer::{build_case_preserving_pattern_set, build_pattern_set, build_regex, load_pattern_map};
+use replacer::{BackupMode, BomMode, EolMode, CaseSensitivity, FileReport, Journal, LineScope, MatchKind, Occurrences, PatternSet, ReplacementConfig, ReportFormat, STDIN_NAME, WalkConfig, Warning, WordBoundary};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Path to the file to modify
-    #[arg(short, long)]
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// The string to search for
-    #[arg(short, long)]
-    pattern: String,
+    /// Path to the file to modify; directories are searched recursively (accepts several paths).
+    /// Use "-" or omit it to read from stdin and write to stdout
+    #[arg(short, long, num_args = 1..)]
+    file: Vec<String>,
 
-    /// The string to replace with
-    #[arg(short, long)]
-    replacement: String,
+    /// The string to search for (repeat -p/-r pairs to replace several strings in one pass)
+    #[arg(short, long, required_unless_present = "map")]
+    pattern: Vec<String>,
+
+    /// The string to replace with (one per --pattern, in the same order)
+    #[arg(short, long, required_unless_present_any = ["map", "list_matches", "count", "files_with_matches"])]
+    replacement: Vec<String>,
+
+    /// Read pattern/replacement pairs from a TSV, CSV or JSON file, applied in a single pass
+    #[arg(long, value_name = "FILE")]
+    map: Option<PathBuf>,
+
+    /// How to choose between several patterns matching at the same place
+    #[arg(long, value_enum, default_value = "leftmost-first", value_name = "KIND")]
+    match_kind: MatchKindChoice,
+
+    /// Treat the pattern as a regular expression; the replacement may refer to capture groups ($1, ${name})
+    #[arg(long)]
+    regex: bool,
+
+    /// Match case-insensitively, with Unicode simple case folding
+    #[arg(short = 'i', long, overrides_with = "smart_case")]
+    ignore_case: bool,
+
+    /// Match case-insensitively unless the pattern contains an uppercase letter
+    #[arg(short = 'S', long, overrides_with = "ignore_case")]
+    smart_case: bool,
+
+    /// Match case-insensitively and give each replacement the casing of the text it replaces
+    /// (foo → bar turns Foo into Bar and FOO into BAR; foo_bar also matches FooBar, fooBar, FOO_BAR)
+    #[arg(long, conflicts_with = "regex")]
+    preserve_case: bool,
+
+    /// Only replace whole words: matches preceded or followed by a word character (Unicode \w) are skipped.
+    /// The short flag is -W, as -w is --output
+    #[arg(short = 'W', long, conflicts_with = "regex")]
+    word: bool,
+
+    /// Characters that make up words for --word, as a regex class (e.g. "[\w-]" for CSS); implies --word
+    #[arg(long, value_name = "CLASS", conflicts_with = "regex")]
+    boundary: Option<String>,
+
+    /// Only replace the first N matches
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), group = "selection")]
+    max_count: Option<u64>,
+
+    /// Only replace the Nth match
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), group = "selection")]
+    nth: Option<u64>,
+
+    /// Only replace the listed matches, e.g. "2..5,8" (ranges are inclusive; "10.." means from the 10th on)
+    #[arg(long, value_name = "LIST", group = "selection")]
+    occurrences: Option<String>,
+
+    /// Whether --max-count, --nth and --occurrences count matches per file or across all files
+    #[arg(long, value_enum, default_value = "file", value_name = "SCOPE")]
+    occurrence_scope: OccurrenceScope,
+
+    /// Only replace on lines START to END, e.g. "10:200" ("10:" runs to the end, ":200" starts at the first line)
+    #[arg(long, value_name = "START:END")]
+    lines: Option<String>,
+
+    /// Only replace on lines matching this regex
+    #[arg(long, value_name = "REGEX")]
+    line_matching: Option<String>,
+
+    /// Only replace on lines not matching this regex
+    #[arg(long, value_name = "REGEX")]
+    line_not_matching: Option<String>,
+
+    /// Only list the matches, as path:line:column: line, without replacing anything
+    #[arg(long, group = "search", conflicts_with_all = ["output", "dry_run", "report"])]
+    list_matches: bool,
+
+    /// Only print the number of matches of each file with any, as path:count, without replacing anything
+    #[arg(short, long, group = "search", conflicts_with_all = ["output", "dry_run", "report"])]
+    count: bool,
+
+    /// Only print the paths of the files with matches, without replacing anything
+    #[arg(short = 'l', long, group = "search", conflicts_with_all = ["output", "dry_run", "report"])]
+    files_with_matches: bool,
 
     /// Display statistics about the replacement (number of replacements, time taken)
     #[arg(long)]
     stat: bool,
 
+    /// Print a machine-readable record per file (path, encoding, replacements, ...) instead of statistics
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "stat")]
+    report: Option<ReportChoice>,
+
     /// Path to the output file. If not specified, the original file will be modified in place.
     #[arg(short = 'w', long)]
     output: Option<String>,
@@ -28,6 +119,39 @@ struct Args {
     #[arg(short, long)]
     encoding: Option<String>,
 
+    /// Treat warnings (unknown encoding label, undecodable bytes, unencodable characters) as errors,
+    /// leaving the file untouched
+    #[arg(long)]
+    strict: bool,
+
+    /// Replace in the raw bytes read as UTF-8 without decoding them; bytes that are not UTF-8 are
+    /// kept exactly as they were
+    #[arg(long, conflicts_with = "encoding")]
+    bytes: bool,
+
+    /// Write the output in this encoding (e.g., UTF-8, UTF-16LE) instead of the one the input was read in
+    #[arg(long, value_name = "ENCODING", value_parser = parse_output_encoding, conflicts_with = "bytes")]
+    output_encoding: Option<&'static encoding_rs::Encoding>,
+
+    /// Whether the output starts with a byte order mark (default: keep the input's)
+    #[arg(long, value_enum, value_name = "MODE", conflicts_with = "bytes")]
+    bom: Option<BomChoice>,
+
+    /// Line endings of the output (default: keep them as they are)
+    #[arg(long, value_enum, value_name = "MODE")]
+    eol: Option<EolChoice>,
+
+    /// Match as if every CRLF were LF, so that a \n in a pattern also matches Windows line endings;
+    /// unless --eol says otherwise, line endings outside replaced text are kept, and those of a
+    /// replacement follow the text it replaces
+    #[arg(long)]
+    eol_agnostic: bool,
+
+    /// Skip files whose detected encoding has a confidence below this value, from 0 to 1, rather
+    /// than guess (valid UTF-8 and files with a BOM are certain)
+    #[arg(long, value_name = "CONFIDENCE", value_parser = parse_confidence, conflicts_with_all = ["encoding", "bytes"])]
+    min_confidence: Option<f32>,
+
     /// Enable parallel processing for large files (default: auto-detect based on file size)
     #[arg(long)]
     parallel: bool,
@@ -44,35 +168,639 @@ struct Args {
     #[arg(long, default_value = "8388608")]
     buffer_size: usize,
 
+    /// Show a unified diff of the changes instead of writing them
+    #[arg(long, visible_alias = "diff")]
+    dry_run: bool,
+
+    /// Number of context lines around each change in the diff
+    #[arg(long, default_value = "3", value_name = "LINES")]
+    diff_context: usize,
+
+    /// When to color the diff output
+    #[arg(long, value_enum, default_value = "auto", value_name = "WHEN")]
+    color: ColorChoice,
+
+    /// Back up each file before modifying it, the GNU way: CONTROL says whether backups are
+    /// numbered (default: existing)
+    #[arg(long, value_enum, value_name = "CONTROL", num_args = 0..=1, require_equals = true, default_missing_value = "existing")]
+    backup: Option<BackupControl>,
+
+    /// Suffix of simple backups (default: "~"); implies --backup
+    #[arg(long, value_name = "SUFFIX")]
+    suffix: Option<String>,
+
+    /// Store backups in this directory, mirroring the files' paths, instead of next to them
+    #[arg(long, value_name = "DIR")]
+    backup_dir: Option<PathBuf>,
+
+    /// Keep the original access and modification times of rewritten files
+    #[arg(long)]
+    preserve_timestamps: bool,
+
+    /// Don't record this run in the undo journal
+    #[arg(long)]
+    no_journal: bool,
+
     /// Threshold for parallel processing (default: 1MB)
     #[arg(long, default_value = "1048576")]
     parallel_threshold: usize,
+
+    /// Only process files matching this glob when searching directories (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files and directories matching this glob when searching directories (can be repeated)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Maximum depth to descend into when searching directories
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// Include hidden files and directories when searching directories
+    #[arg(long)]
+    hidden: bool,
+
+    /// Don't respect .gitignore, .ignore and global git exclude files when searching directories
+    #[arg(long)]
+    no_ignore: bool,
+}
+
+impl Args {
+    /// Whether this run only searches, with --list-matches, --count or --files-with-matches
+    fn searching(&self) -> bool {
+        self.list_matches || self.count || self.files_with_matches
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Restore the files modified by a previous run (the latest one by default)
+    Undo {
+        /// Identifier of the run to undo, as shown by --list
+        run_id: Option<String>,
+
+        /// List the runs that can be undone instead
+        #[arg(long)]
+        list: bool,
+
+        /// Restore the files even if they changed since the run
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum MatchKindChoice {
+    /// The leftmost match; the pattern given first wins at the same position
+    LeftmostFirst,
+    /// The leftmost match; the longest pattern wins at the same position
+    LeftmostLongest,
+    /// The match that ends first
+    Standard,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OccurrenceScope {
+    /// Numbering restarts with each file
+    File,
+    /// Matches are numbered across all files, in path order
+    Global,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum BomChoice {
+    /// Write a BOM if the input had one
+    Keep,
+    /// Write a BOM whenever the output encoding has one (UTF-8, UTF-16)
+    Add,
+    /// Never write a BOM
+    Remove,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum EolChoice {
+    /// Leave line endings as they are
+    Keep,
+    /// Write every line ending as LF
+    Lf,
+    /// Write every line ending as CRLF
+    Crlf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ReportChoice {
+    /// A JSON array, written once every file has been processed
+    Json,
+    /// One JSON object per line and per file
+    Ndjson,
+}
+
+/// The GNU version control values of `--backup`
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum BackupControl {
+    /// Never make backups, even with --suffix or --backup-dir
+    #[value(alias = "off")]
+    None,
+    /// Make numbered backups: file.~1~, file.~2~, ...
+    #[value(alias = "t")]
+    Numbered,
+    /// Make numbered backups of files that already have some, simple ones otherwise
+    #[value(alias = "nil")]
+    Existing,
+    /// Make a single backup, named with --suffix
+    #[value(alias = "never")]
+    Simple,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+
+    if let Some(Command::Undo { run_id, list, force }) = &args.command {
+        return run_undo(run_id.as_deref(), *list, *force);
+    }
+    let word_boundary = word_boundary(&args)?;
+    let pattern_set = build_pattern_set_from_args(&args, word_boundary.as_ref())?;
+    let (pattern, replacement) = match &pattern_set {
+        Some(_) if args.pattern.len() != 1 || args.map.is_some() => ("", ""),
+        _ => (args.pattern[0].as_str(), args.replacement.first().map_or("", String::as_str)),
+    };
+
+    // Every run that writes files is journaled so that it can be undone
+    let journal = if args.dry_run || args.no_journal || args.searching() {
+        None
+    } else {
+        match Journal::create() {
+            Ok(journal) => Some(Arc::new(journal)),
+            Err(e) => {
+                eprintln!("Warning: Cannot create the undo journal ({}). This run cannot be undone.", e);
+                None
+            }
+        }
+    };
 
     // Build configuration
     let mut config = ReplacementConfig::new()
         .with_buffer_size(args.buffer_size)
         .with_parallel_threshold(args.parallel_threshold)
         .with_ascii_optimization(args.ascii_opt)
-        .with_caching(!args.no_cache);
+        .with_caching(!args.no_cache)
+        .with_regex(args.regex)
+        .with_case_sensitivity(case_sensitivity(&args))
+        .with_word_boundary(word_boundary)
+        .with_occurrences(occurrences(&args)?)
+        .with_line_scope(line_scope(&args)?)
+        .with_pattern_set(pattern_set)
+        .with_dry_run(args.dry_run)
+        .with_preserve_timestamps(args.preserve_timestamps)
+        .with_backup(backup_mode(&args))
+        .with_backup_dir(args.backup_dir.clone())
+        .with_strict(args.strict)
+        .with_byte_mode(args.bytes)
+        .with_min_confidence(args.min_confidence)
+        .with_output_encoding(args.output_encoding)
+        .with_bom(match args.bom {
+            Some(BomChoice::Add) => BomMode::Add,
+            Some(BomChoice::Remove) => BomMode::Remove,
+            Some(BomChoice::Keep) | None => BomMode::Keep,
+        })
+        .with_eol(match args.eol {
+            Some(EolChoice::Lf) => EolMode::Lf,
+            Some(EolChoice::Crlf) => EolMode::Crlf,
+            Some(EolChoice::Keep) | None => EolMode::Keep,
+        })
+        .with_eol_agnostic(args.eol_agnostic)
+        .with_report(report_format(&args))
+        .with_diff_context(args.diff_context)
+        .with_diff_color(match args.color {
+            ColorChoice::Auto => std::io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        });
 
     // Override parallel setting if explicitly specified
     if args.parallel {
         config.parallel_threshold = 0; // Force parallel processing
     }
 
-    replace_in_file(
-        &args.file,
-        &args.pattern,
-        &args.replacement,
-        args.output.as_deref(),
-        args.encoding.as_deref(),
-        args.stat,
-        Some(config),
-    )?;
+    let read_stdin = args.file.is_empty() || args.file == ["-"];
+    if args.file.len() > 1 && args.file.iter().any(|file| file == "-") {
+        return Err("stdin (\"-\") cannot be combined with other input paths".into());
+    }
+    if args.file.is_empty() && std::io::stdin().is_terminal() {
+        return Err("no input: give --file, or pipe data to stdin".into());
+    }
+
+    if args.searching() {
+        if !run_search(&args, pattern, &config, read_stdin)? {
+            // Like grep, finding nothing is reported by the exit status
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let result = run_replace(&args, pattern, replacement, config, journal.clone(), read_stdin);
+
+    // The files written before a failure can still be undone, and a run writing none is discarded
+    if let Some(journal) = journal.and_then(Arc::into_inner) {
+        let run_id = journal.run_id().to_string();
+        if journal.finish()? && args.stat {
+            println!("Undo with: replacer undo {}", run_id);
+        }
+    }
+
+    let failed = result?;
+    if failed > 0 {
+        return Err(format!("{} file(s) could not be processed", failed).into());
+    }
+    Ok(())
+}
 
+/// Replace in stdin, a single file or several files, returning how many files could not be processed
+fn run_replace(
+    args: &Args,
+    pattern: &str,
+    replacement: &str,
+    config: ReplacementConfig,
+    journal: Option<Arc<Journal>>,
+    read_stdin: bool
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if read_stdin {
+        let report = replace_stdin(
+            pattern,
+            replacement,
+            args.output.as_deref(),
+            args.encoding.as_deref(),
+            args.stat,
+            Some(config),
+            journal,
+        ).inspect_err(|e| report_failure(args, STDIN_NAME, e))?;
+        print_warnings(args, STDIN_NAME, &report);
+    } else if args.file.len() == 1 && !Path::new(&args.file[0]).is_dir() {
+        // A single regular file keeps the original single-file behavior
+        let report = replace_in_file(
+            &args.file[0],
+            pattern,
+            replacement,
+            args.output.as_deref(),
+            args.encoding.as_deref(),
+            args.stat,
+            Some(config),
+            journal,
+        ).inspect_err(|e| report_failure(args, &args.file[0], e))?;
+        print_warnings(args, &report.path, &report);
+    } else if args.output.is_some() {
+        return Err("--output can only be used with a single input file".into());
+    } else {
+        return run_multi_file(args, pattern, replacement, &config, journal.as_deref());
+    }
+    Ok(0)
+}
+
+fn run_undo(run_id: Option<&str>, list: bool, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let state_dir = replacer::io::journal::state_dir()
+        .ok_or("no state directory: set XDG_STATE_HOME or HOME")?;
+
+    if list {
+        for run in list_runs(&state_dir)? {
+            println!("{}  {} file(s)", run.run_id, run.files);
+        }
+        return Ok(());
+    }
+
+    let outcome = undo_run(&state_dir, run_id, force)?;
+    for path in &outcome.restored {
+        println!("Restored {}", path.display());
+    }
+    for path in &outcome.removed {
+        println!("Removed {}", path.display());
+    }
+    println!("Undid run {}", outcome.run_id);
     Ok(())
 }
+
+/// Several -p/-r pairs, a mapping file or --preserve-case are compiled into a single pattern set;
+/// otherwise a lone pair keeps the single pattern engines
+fn build_pattern_set_from_args(
+    args: &Args,
+    word_boundary: Option<&WordBoundary>
+) -> Result<Option<Arc<PatternSet>>, Box<dyn std::error::Error>> {
+    // A search has no use for replacements
+    if args.pattern.len() != args.replacement.len() && !(args.searching() && args.replacement.is_empty()) {
+        return Err(format!(
+            "each --pattern needs a --replacement ({} patterns, {} replacements)",
+            args.pattern.len(),
+            args.replacement.len()
+        ).into());
+    }
+    if args.pattern.len() == 1 && args.map.is_none() && !args.preserve_case {
+        return Ok(None);
+    }
+    if args.regex {
+        return Err("--regex takes a single pattern; it cannot be combined with several patterns or --map".into());
+    }
+
+    let replacements = args.replacement.iter().cloned().chain(std::iter::repeat(String::new()));
+    let mut pairs: Vec<(String, String)> = args.pattern.iter().cloned().zip(replacements).collect();
+    if let Some(map) = &args.map {
+        pairs.extend(load_pattern_map(map)?);
+    }
+    let kind = match args.match_kind {
+        MatchKindChoice::LeftmostFirst => MatchKind::LeftmostFirst,
+        MatchKindChoice::LeftmostLongest => MatchKind::LeftmostLongest,
+        MatchKindChoice::Standard => MatchKind::Standard,
+    };
+    let set = if args.preserve_case {
+        build_case_preserving_pattern_set(&pairs, kind)?
+    } else {
+        let ignore_case = case_sensitivity(args).ignores_case(pairs.iter().map(|(pattern, _)| pattern.as_str()));
+        build_pattern_set(&pairs, kind, ignore_case)?
+    };
+    Ok(Some(Arc::new(set.with_word_boundary(word_boundary.cloned())?)))
+}
+
+fn parse_output_encoding(label: &str) -> Result<&'static encoding_rs::Encoding, String> {
+    match encoding_rs::Encoding::for_label(label.as_bytes()) {
+        // The replacement encoding only decodes
+        Some(encoding) if encoding != encoding_rs::REPLACEMENT => Ok(encoding),
+        _ => Err(format!("unknown output encoding label '{}'", label)),
+    }
+}
+
+fn parse_confidence(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(confidence) if (0.0..=1.0).contains(&confidence) => Ok(confidence),
+        _ => Err(format!("'{}' is not a confidence between 0 and 1", value)),
+    }
+}
+
+fn occurrences(args: &Args) -> std::io::Result<Option<Occurrences>> {
+    let occurrences = if let Some(count) = args.max_count {
+        Occurrences::first(count as usize)
+    } else if let Some(n) = args.nth {
+        Occurrences::nth(n as usize)
+    } else if let Some(list) = &args.occurrences {
+        Occurrences::parse(list)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(occurrences.with_global_numbering(args.occurrence_scope == OccurrenceScope::Global)))
+}
+
+fn line_scope(args: &Args) -> std::io::Result<Option<LineScope>> {
+    if args.lines.is_none() && args.line_matching.is_none() && args.line_not_matching.is_none() {
+        return Ok(None);
+    }
+    let scope = match &args.lines {
+        Some(range) => LineScope::parse(range)?,
+        None => LineScope::all(),
+    };
+    Ok(Some(scope
+        .with_matching(args.line_matching.as_deref().map(build_regex).transpose()?)
+        .with_not_matching(args.line_not_matching.as_deref().map(build_regex).transpose()?)))
+}
+
+fn word_boundary(args: &Args) -> std::io::Result<Option<WordBoundary>> {
+    match &args.boundary {
+        Some(class) => WordBoundary::from_class(class).map(Some),
+        None if args.word => Ok(Some(WordBoundary::unicode())),
+        None => Ok(None),
+    }
+}
+
+fn case_sensitivity(args: &Args) -> CaseSensitivity {
+    if args.ignore_case {
+        CaseSensitivity::Insensitive
+    } else if args.smart_case {
+        CaseSensitivity::Smart
+    } else {
+        CaseSensitivity::Sensitive
+    }
+}
+
+/// Any backup option enables backups, as `existing` unless --backup says otherwise
+fn backup_mode(args: &Args) -> BackupMode {
+    let implied = args.suffix.is_some() || args.backup_dir.is_some();
+    let suffix = || args.suffix.clone().unwrap_or_else(|| "~".to_string());
+    match args.backup.or(implied.then_some(BackupControl::Existing)) {
+        None | Some(BackupControl::None) => BackupMode::None,
+        Some(BackupControl::Numbered) => BackupMode::Numbered,
+        Some(BackupControl::Existing) => BackupMode::Existing(suffix()),
+        Some(BackupControl::Simple) => BackupMode::Simple(suffix()),
+    }
+}
+
+/// Process every file, going on past the ones that fail
+///
+/// Returns the number of files that failed, each reported on stderr.
+fn run_multi_file(
+    args: &Args,
+    pattern: &str,
+    replacement: &str,
+    config: &ReplacementConfig,
+    journal: Option<&Journal>
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+
+    let files = collect_input_files(args)?;
+    let file_refs: Vec<&Path> = files.iter().map(|f| f.as_path()).collect();
+    let mut results = Vec::with_capacity(files.len());
+    let mut failed = 0;
+    let outcomes = process_files_parallel(&file_refs, pattern, replacement, encoding(args)?, config, journal)?;
+    for (path, outcome) in file_refs.iter().zip(outcomes) {
+        match outcome {
+            Ok(report) => results.push(report),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                // Kept in the report, in input order, but out of the statistics
+                results.push(FileReport::failed(path.display().to_string(), &e));
+                failed += 1;
+            }
+        }
+    }
+
+    // Diffs come in input order, once every file has been processed
+    let mut stdout = std::io::stdout().lock();
+    for diff in results.iter().filter_map(|result| result.diff.as_ref()) {
+        stdout.write_all(diff.as_bytes())?;
+    }
+    drop(stdout);
+
+    if let Some(format) = config.report {
+        // A dry run prints its diffs on stdout
+        if args.dry_run {
+            write_report(&mut std::io::stderr().lock(), &results, format)?;
+        } else {
+            write_report(&mut std::io::stdout().lock(), &results, format)?;
+        }
+        return Ok(failed);
+    }
+    for result in &results {
+        print_warnings(args, &result.path, result);
+    }
+
+    results.retain(|result| result.error.is_none());
+    if args.stat {
+        let modified: Vec<_> = results.iter().filter(|result| result.replacements > 0).collect();
+        let ambiguous: Vec<_> = results.iter().filter(|result| result.skipped == Some("ambiguous encoding")).collect();
+        for result in &modified {
+            match (result.encoding, result.confidence) {
+                // Only a guessed encoding is worth pointing out
+                (Some(encoding), Some(confidence)) if confidence < 1.0 => println!(
+                    "{}: {} replacements ({}, confidence {:.2})", result.path, result.replacements, encoding, confidence
+                ),
+                _ => println!("{}: {} replacements", result.path, result.replacements),
+            }
+        }
+        for result in &ambiguous {
+            println!("{}: skipped, ambiguous encoding ({}, confidence {:.2})",
+                result.path, result.encoding.unwrap_or("unknown"), result.confidence.unwrap_or(0.0));
+        }
+        println!("----- Statistics -----");
+        println!("Files processed: {}", results.len());
+        if failed > 0 {
+            println!("Files failed: {}", failed);
+        }
+        if !ambiguous.is_empty() {
+            println!("Files skipped (ambiguous encoding): {}", ambiguous.len());
+        }
+        if args.dry_run {
+            println!("Files that would be modified: {}", modified.len());
+        } else {
+            println!("Files modified: {}", modified.len());
+        }
+        println!("Replacements made: {}", modified.iter().map(|result| result.replacements).sum::<usize>());
+        println!("Time taken: {:.2?} ", start_time.elapsed());
+        println!("----------------------");
+    }
+
+    Ok(failed)
+}
+
+/// With --report, the record of an input that could not be processed, written where its report
+/// would have gone
+fn report_failure(args: &Args, path: &str, error: &replacer::Error) {
+    let Some(format) = report_format(args) else { return };
+    let record = [FileReport::failed(path, error)];
+    // Stdout carries a diff, or the data read from stdin unless it goes to a file
+    let data_on_stdout = path == STDIN_NAME && args.output.as_deref().is_none_or(|output| output == "-");
+    // The error itself is already on its way to stderr
+    let _ = if args.dry_run || data_on_stdout {
+        write_report(&mut std::io::stderr().lock(), &record, format)
+    } else {
+        write_report(&mut std::io::stdout().lock(), &record, format)
+    };
+}
+
+fn report_format(args: &Args) -> Option<ReportFormat> {
+    args.report.map(|format| match format {
+        ReportChoice::Json => ReportFormat::Json,
+        ReportChoice::Ndjson => ReportFormat::Ndjson,
+    })
+}
+
+/// Search instead of replacing: print the matches, their count or the files with any
+///
+/// Returns whether anything matched.
+fn run_search(
+    args: &Args,
+    pattern: &str,
+    config: &ReplacementConfig,
+    read_stdin: bool
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let results = if read_stdin {
+        vec![search_reader(&mut std::io::stdin().lock(), STDIN_NAME, pattern, encoding(args)?, config)?]
+    } else {
+        let files = collect_input_files(args)?;
+        let file_refs: Vec<&Path> = files.iter().map(|f| f.as_path()).collect();
+        search_files_parallel(&file_refs, pattern, encoding(args)?, config)?
+    };
+
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    for result in &results {
+        for warning in &result.warnings {
+            eprintln!("Warning: {}: {}", result.path, warning);
+        }
+        if result.matches.is_empty() {
+            continue;
+        }
+        if args.count {
+            writeln!(stdout, "{}:{}", result.path, result.matches.len())?;
+        } else if args.files_with_matches {
+            writeln!(stdout, "{}", result.path)?;
+        } else {
+            for location in &result.matches {
+                writeln!(stdout, "{}:{}:{}: {}", result.path, location.line, location.column, location.context)?;
+            }
+        }
+    }
+    stdout.flush()?;
+
+    Ok(results.iter().any(|result| !result.matches.is_empty()))
+}
+
+/// The files to process: the given paths, with directories searched recursively
+fn collect_input_files(args: &Args) -> std::io::Result<Vec<PathBuf>> {
+    let walk_config = WalkConfig::new()
+        .with_include(args.include.clone())
+        .with_exclude(args.exclude.clone())
+        .with_max_depth(args.max_depth)
+        .with_hidden(args.hidden)
+        .with_ignore_files(!args.no_ignore);
+    let roots: Vec<PathBuf> = args.file.iter().map(PathBuf::from).collect();
+    collect_files(&roots, &walk_config)
+}
+
+/// The encoding given with --encoding, if any; `None` means it is detected for each file
+fn encoding(args: &Args) -> Result<Option<&'static encoding_rs::Encoding>, replacer::Error> {
+    let Some(label) = args.encoding.as_deref() else {
+        return Ok(None);
+    };
+    if let Some(encoding) = encoding_rs::Encoding::for_label(label.as_bytes()) {
+        return Ok(Some(encoding));
+    }
+    let warning = replacer::Warning::UnknownEncoding { label: label.to_string() };
+    if args.strict {
+        return Err(replacer::Error::Warning { path: None, warning });
+    }
+    eprintln!("Warning: {}", warning);
+    Ok(Some(encoding_rs::UTF_8))
+}
+
+/// The warnings of a file, unless they are part of the report
+fn print_warnings(args: &Args, path: &str, report: &FileReport) {
+    if args.report.is_some() {
+        return;
+    }
+    if report.skipped == Some("ambiguous encoding") {
+        eprintln!("Warning: {}: Skipped: its encoding was guessed as '{}' with a confidence of only {:.2}.",
+            path, report.encoding.unwrap_or("unknown"), report.confidence.unwrap_or(0.0));
+    }
+    if report.diff_skipped {
+        eprintln!("Warning: {}: Streamed as it is too large to diff; the dry run only counted its {} replacements.",
+            path, report.replacements);
+    }
+    for warning in &report.warnings {
+        // The file was not decoded with the guess after all
+        if report.skipped.is_some() && matches!(warning, Warning::GuessedEncoding { .. }) {
+            continue;
+        }
+        eprintln!("Warning: {}: {}", path, warning);
+    }
+}